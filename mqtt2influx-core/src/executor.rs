@@ -1,4 +1,5 @@
-use crate::{EventSink, EventSource};
+use crate::utils::Backoff;
+use crate::{Event, EventSink, EventSource, Metrics, Shutdown, SinkError, SourceEvent};
 use anyhow::Result;
 
 pub struct Executor;
@@ -7,18 +8,90 @@ impl Executor {
     pub async fn run<Source, Sink>(source: Source, sink: &Sink) -> Result<()>
     where
         Source: EventSource,
-        Sink: EventSink,
+        Sink: EventSink + ?Sized,
+    {
+        Self::run_with_metrics(source, sink, &Metrics::default()).await
+    }
+
+    /// Same as `run`, counting the events taken from the source and whether the sink stored them.
+    pub async fn run_with_metrics<Source, Sink>(source: Source, sink: &Sink, metrics: &Metrics) -> Result<()>
+    where
+        Source: EventSource,
+        Sink: EventSink + ?Sized,
+    {
+        Self::run_with_retries(source, sink, metrics, &Backoff::default(), &Shutdown::default()).await
+    }
+
+    /// Same as `run_with_metrics`, waiting `backoff` between the attempts to sink an event that failed with a
+    /// retryable error, until `shutdown` is triggered.
+    pub async fn run_with_retries<Source, Sink>(
+        source: Source,
+        sink: &Sink,
+        metrics: &Metrics,
+        backoff: &Backoff,
+        shutdown: &Shutdown,
+    ) -> Result<()>
+    where
+        Source: EventSource,
+        Sink: EventSink + ?Sized,
     {
         info!("Starting EventSource");
         let mut rx = source.start().await?;
 
         info!("Receiving events");
-        while let Some(event) = rx.recv().await {
+        while let Some(SourceEvent { event, ack }) = rx.recv().await {
+            metrics.event_dequeued();
             info!("Event received: {:?}", event);
-            if let Err(e) = sink.sink(event).await {
-                error!("Error sinking event: {}", e);
+            if !Self::sink(event, sink, metrics, backoff, shutdown).await {
+                // Once shut down, the source disconnects and the broker redelivers QoS 1/2 messages not acknowledged
+                // on a persistent session
+                continue;
+            }
+            if let Some(ack) = ack {
+                if let Err(e) = ack.ack().await {
+                    error!("Error acknowledging event: {}", e);
+                }
             }
         }
         Ok(())
     }
+
+    /// Sinks `event`, retrying it while it fails with a retryable error. Returns whether its message can be
+    /// acknowledged, which is not the case when the retries were given up because of the shutdown.
+    ///
+    /// The broker only redelivers unacknowledged messages on reconnect, and stops delivering once its inflight window
+    /// is full of them, so events are never left unacknowledged while connected. Events failing with an error that
+    /// would happen again, such as a rejected point, are acknowledged and counted as failed.
+    async fn sink<Sink>(event: Event, sink: &Sink, metrics: &Metrics, backoff: &Backoff, shutdown: &Shutdown) -> bool
+    where
+        Sink: EventSink + ?Sized,
+    {
+        let mut attempt = 1;
+        loop {
+            let e = match sink.sink(event.clone()).await {
+                Ok(_) => {
+                    metrics.event_sunk();
+                    return true;
+                }
+                Err(e) => e,
+            };
+            if !SinkError::find(&e).map(SinkError::is_retryable).unwrap_or(false) {
+                metrics.event_failed();
+                error!("Error sinking event, dropping it: {}", e);
+                return true;
+            }
+            if shutdown.is_triggered() {
+                metrics.event_failed();
+                error!("Error sinking event, not retrying it while shutting down: {}", e);
+                return false;
+            }
+            let delay = backoff.delay(attempt);
+            warn!("Error sinking event: {}. Retrying in {:?} [attempt={}]", e, delay, attempt);
+            tokio::select! {
+                _ = tokio::time::sleep(delay) => {}
+                _ = shutdown.triggered() => {}
+            }
+            attempt += 1;
+        }
+    }
 }
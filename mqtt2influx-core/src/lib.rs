@@ -4,25 +4,95 @@ extern crate tracing;
 use thiserror::Error;
 
 pub mod executor;
+pub mod metrics;
 pub mod services;
+pub mod shutdown;
+pub mod topic;
 pub mod types;
 pub mod utils;
 
 pub use executor::*;
+pub use metrics::*;
 pub use services::*;
+pub use shutdown::*;
 pub use types::*;
 
 pub use anyhow;
 pub use async_trait;
 pub use chrono;
+pub use serde_json;
 pub use tokio_compat_02;
 
 #[derive(Clone, Debug, Error)]
 pub enum AppError {
+    #[error("Configuration error: {0}")]
+    Config(String),
+    #[error("InfluxDB error: {0}")]
+    Influx(String),
     #[error("Mqtt error: {0}")]
     Mqtt(String),
+    #[error("Payload error: {0}")]
+    Payload(String),
     #[error("Server error: {0}")]
     Server(String),
+    #[error("Sink error: {0}")]
+    Sink(#[from] SinkError),
+    #[error("TLS error: {0}")]
+    Tls(String),
+    #[error("Topic error: {0}")]
+    Topic(String),
     #[error("Unknown error: {0}")]
     Unknown(String),
 }
+
+/// Failure reported by a sink when writing to its backend.
+#[derive(Clone, Debug, Error, PartialEq, Eq)]
+pub enum SinkError {
+    /// The backend could not be reached, or the connection failed mid-request
+    #[error("Network error: {0}")]
+    Network(String),
+    /// The backend rejected the credentials (401/403)
+    #[error("Authentication error [status={status}]: {message}")]
+    Auth { status: u16, message: String },
+    /// The backend rejected the request itself (4xx), e.g. a field type conflict or an unknown database
+    #[error("Rejected request [status={status}]: {message}")]
+    Rejected { status: u16, message: String },
+    /// The backend failed to handle the request (5xx)
+    #[error("Backend error [status={status}]: {message}")]
+    Backend { status: u16, message: String },
+}
+
+impl SinkError {
+    /// Classifies an unsuccessful HTTP status.
+    pub fn from_status(status: u16, message: String) -> Self {
+        match status {
+            401 | 403 => SinkError::Auth { status, message },
+            400..=499 => SinkError::Rejected { status, message },
+            _ => SinkError::Backend { status, message },
+        }
+    }
+
+    /// Finds the sink error behind `e`, if any.
+    pub fn find(e: &anyhow::Error) -> Option<&SinkError> {
+        e.chain().find_map(|cause| cause.downcast_ref::<SinkError>())
+    }
+
+    /// Short name of the error kind, as used in the configuration.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            SinkError::Network(_) => "network",
+            SinkError::Auth { .. } => "auth",
+            SinkError::Rejected { .. } => "rejected",
+            SinkError::Backend { .. } => "backend",
+        }
+    }
+
+    /// Whether sending the same request again may succeed. Rejected requests only are when the backend asked to slow down.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            SinkError::Network(_) | SinkError::Backend { .. } => true,
+            SinkError::Rejected { status, .. } => *status == 408 || *status == 429,
+            SinkError::Auth { .. } => false,
+        }
+    }
+}
@@ -0,0 +1,275 @@
+use crate::QueueStats;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Upper bounds, in seconds, of the sink latency histogram buckets.
+pub const LATENCY_BUCKETS: [f64; 12] = [0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+#[derive(Clone, Debug, Default)]
+struct Histogram {
+    /// Observations per bucket, the last one being `+Inf`
+    buckets: [u64; LATENCY_BUCKETS.len() + 1],
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, value: f64) {
+        let bucket = LATENCY_BUCKETS.iter().position(|le| value <= *le).unwrap_or(LATENCY_BUCKETS.len());
+        self.buckets[bucket] += 1;
+        self.sum += value;
+        self.count += 1;
+    }
+}
+
+#[derive(Default)]
+struct Registry {
+    messages_received: Mutex<BTreeMap<String, u64>>,
+    parse_failures: Mutex<BTreeMap<String, u64>>,
+    events_sunk: AtomicU64,
+    events_failed: AtomicU64,
+    sink_errors: Mutex<BTreeMap<String, u64>>,
+    sink_filtered: Mutex<BTreeMap<String, u64>>,
+    sink_latency: Mutex<BTreeMap<String, Histogram>>,
+    mqtt_connected: AtomicBool,
+    mqtt_reconnects: AtomicU64,
+    channel_depth: AtomicI64,
+}
+
+/// Counters of the bridge, rendered in the Prometheus text format. Clones share the same counters.
+#[derive(Clone, Default)]
+pub struct Metrics {
+    registry: Arc<Registry>,
+}
+
+fn increment(counters: &Mutex<BTreeMap<String, u64>>, label: &str) {
+    let mut counters = counters.lock().expect("Metrics lock poisoned");
+    *counters.entry(label.to_string()).or_insert(0) += 1;
+}
+
+/// Escapes a label value as required by the text format.
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+fn header(out: &mut String, name: &str, metric_type: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, metric_type);
+}
+
+fn labelled<T: std::fmt::Display>(out: &mut String, name: &str, label: &str, samples: impl IntoIterator<Item = (String, T)>) {
+    for (value, sample) in samples {
+        let _ = writeln!(out, "{}{{{}=\"{}\"}} {}", name, label, escape(&value), sample);
+    }
+}
+
+impl Metrics {
+    pub fn message_received(&self, topic: &str) {
+        increment(&self.registry.messages_received, topic);
+    }
+
+    pub fn parse_failed(&self, topic: &str) {
+        increment(&self.registry.parse_failures, topic);
+    }
+
+    pub fn event_sunk(&self) {
+        self.registry.events_sunk.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn event_failed(&self) {
+        self.registry.events_failed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Records a call to the sink named `sink`, which took `elapsed` and failed when `failed` is true.
+    pub fn sink_called(&self, sink: &str, elapsed: Duration, failed: bool) {
+        if failed {
+            increment(&self.registry.sink_errors, sink);
+        }
+        let mut latency = self.registry.sink_latency.lock().expect("Metrics lock poisoned");
+        latency.entry(sink.to_string()).or_default().observe(elapsed.as_secs_f64());
+    }
+
+    /// Records an event that the filter of the sink named `sink` did not let through.
+    pub fn event_filtered(&self, sink: &str) {
+        increment(&self.registry.sink_filtered, sink);
+    }
+
+    pub fn set_mqtt_connected(&self, connected: bool) {
+        self.registry.mqtt_connected.store(connected, Ordering::Relaxed);
+    }
+
+    pub fn mqtt_reconnecting(&self) {
+        self.registry.mqtt_reconnects.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Called by the source before sending an event to the executor, which calls `event_dequeued` once it takes it.
+    /// The source calls `event_dequeued` itself when the event could not be sent.
+    pub fn event_queued(&self) {
+        self.registry.channel_depth.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn event_dequeued(&self) {
+        self.registry.channel_depth.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    pub fn messages_received(&self, topic: &str) -> u64 {
+        let counters = self.registry.messages_received.lock().expect("Metrics lock poisoned");
+        counters.get(topic).copied().unwrap_or(0)
+    }
+
+    pub fn events_sunk(&self) -> u64 {
+        self.registry.events_sunk.load(Ordering::Relaxed)
+    }
+
+    pub fn sink_errors(&self, sink: &str) -> u64 {
+        let counters = self.registry.sink_errors.lock().expect("Metrics lock poisoned");
+        counters.get(sink).copied().unwrap_or(0)
+    }
+
+    pub fn sink_filtered(&self, sink: &str) -> u64 {
+        let counters = self.registry.sink_filtered.lock().expect("Metrics lock poisoned");
+        counters.get(sink).copied().unwrap_or(0)
+    }
+
+    /// Every metric in the Prometheus text format, plus the depth of the given sink queues.
+    pub fn render(&self, queues: &[QueueStats]) -> String {
+        let registry = &self.registry;
+        let mut out = String::new();
+
+        header(
+            &mut out,
+            "mqtt2influx_messages_received_total",
+            "counter",
+            "MQTT messages received per topic",
+        );
+        let received = registry.messages_received.lock().expect("Metrics lock poisoned").clone();
+        labelled(&mut out, "mqtt2influx_messages_received_total", "topic", received);
+
+        header(
+            &mut out,
+            "mqtt2influx_parse_failures_total",
+            "counter",
+            "MQTT messages whose payload is not a JSON object, per topic",
+        );
+        let failures = registry.parse_failures.lock().expect("Metrics lock poisoned").clone();
+        labelled(&mut out, "mqtt2influx_parse_failures_total", "topic", failures);
+
+        header(&mut out, "mqtt2influx_events_sunk_total", "counter", "Events stored by the sinks");
+        let _ = writeln!(
+            out,
+            "mqtt2influx_events_sunk_total {}",
+            registry.events_sunk.load(Ordering::Relaxed)
+        );
+        header(
+            &mut out,
+            "mqtt2influx_events_failed_total",
+            "counter",
+            "Events the sinks failed to store",
+        );
+        let _ = writeln!(
+            out,
+            "mqtt2influx_events_failed_total {}",
+            registry.events_failed.load(Ordering::Relaxed)
+        );
+
+        header(&mut out, "mqtt2influx_sink_errors_total", "counter", "Failed calls per sink");
+        let errors = registry.sink_errors.lock().expect("Metrics lock poisoned").clone();
+        labelled(&mut out, "mqtt2influx_sink_errors_total", "sink", errors);
+
+        header(
+            &mut out,
+            "mqtt2influx_sink_filtered_total",
+            "counter",
+            "Events filtered out of each sink",
+        );
+        let filtered = registry.sink_filtered.lock().expect("Metrics lock poisoned").clone();
+        labelled(&mut out, "mqtt2influx_sink_filtered_total", "sink", filtered);
+
+        header(
+            &mut out,
+            "mqtt2influx_sink_duration_seconds",
+            "histogram",
+            "Time taken by each sink to store an event",
+        );
+        let latency = registry.sink_latency.lock().expect("Metrics lock poisoned").clone();
+        for (sink, histogram) in latency {
+            let sink = escape(&sink);
+            let mut cumulative = 0;
+            for (i, count) in histogram.buckets.iter().enumerate() {
+                cumulative += count;
+                let le = LATENCY_BUCKETS.get(i).map(f64::to_string).unwrap_or_else(|| "+Inf".to_string());
+                let _ = writeln!(
+                    out,
+                    "mqtt2influx_sink_duration_seconds_bucket{{sink=\"{}\",le=\"{}\"}} {}",
+                    sink, le, cumulative
+                );
+            }
+            let _ = writeln!(out, "mqtt2influx_sink_duration_seconds_sum{{sink=\"{}\"}} {}", sink, histogram.sum);
+            let _ = writeln!(
+                out,
+                "mqtt2influx_sink_duration_seconds_count{{sink=\"{}\"}} {}",
+                sink, histogram.count
+            );
+        }
+
+        header(
+            &mut out,
+            "mqtt2influx_mqtt_connected",
+            "gauge",
+            "1 when connected to the MQTT broker",
+        );
+        let _ = writeln!(
+            out,
+            "mqtt2influx_mqtt_connected {}",
+            registry.mqtt_connected.load(Ordering::Relaxed) as u8
+        );
+        header(
+            &mut out,
+            "mqtt2influx_mqtt_reconnects_total",
+            "counter",
+            "Reconnection attempts to the MQTT broker",
+        );
+        let _ = writeln!(
+            out,
+            "mqtt2influx_mqtt_reconnects_total {}",
+            registry.mqtt_reconnects.load(Ordering::Relaxed)
+        );
+
+        header(
+            &mut out,
+            "mqtt2influx_channel_depth",
+            "gauge",
+            "Events sent by the MQTT source and not yet taken by the executor",
+        );
+        let _ = writeln!(out, "mqtt2influx_channel_depth {}", registry.channel_depth.load(Ordering::Relaxed));
+
+        header(
+            &mut out,
+            "mqtt2influx_sink_queue_depth",
+            "gauge",
+            "Events waiting in the queue of a sink",
+        );
+        labelled(
+            &mut out,
+            "mqtt2influx_sink_queue_depth",
+            "sink",
+            queues.iter().map(|q| (q.name.clone(), q.depth)),
+        );
+        header(
+            &mut out,
+            "mqtt2influx_sink_queue_dropped_total",
+            "counter",
+            "Events dropped by the full queue of a sink",
+        );
+        labelled(
+            &mut out,
+            "mqtt2influx_sink_queue_dropped_total",
+            "sink",
+            queues.iter().map(|q| (q.name.clone(), q.dropped)),
+        );
+        out
+    }
+}
@@ -1,56 +1,163 @@
 use crate::types::*;
+use crate::utils::Backoff;
 use crate::AppError;
+use crate::Metrics;
+use crate::MqttTlsParameters;
+use crate::Shutdown;
 use anyhow::Result;
-use rumqttc::{Event as MqttEvent, EventLoop, Incoming, MqttOptions, Publish, QoS, Request, Subscribe};
+use chrono::Utc;
+use rumqttc::{AsyncClient, Event as MqttEvent, EventLoop, Incoming, MqttOptions, Outgoing, Publish, QoS, SubscribeFilter, Transport};
+use std::fmt;
+use std::time::Duration;
 use tokio::sync::mpsc::{channel, Receiver, Sender};
+use tokio::sync::watch;
 
 #[async_trait::async_trait]
 pub trait EventSource {
-    async fn start(self) -> Result<Receiver<Event>>;
+    async fn start(self) -> Result<Receiver<SourceEvent>>;
+}
+
+/// An event as produced by a source, along with the handle to acknowledge it once it has been sunk.
+#[derive(Debug)]
+pub struct SourceEvent {
+    pub event: Event,
+    pub ack: Option<Ack>,
+}
+
+impl From<Event> for SourceEvent {
+    fn from(event: Event) -> Self {
+        Self { event, ack: None }
+    }
+}
+
+/// Confirms a QoS 1/2 message to the broker. Dropping it without acking leaves the message to be redelivered.
+pub struct Ack {
+    client: AsyncClient,
+    publish: Publish,
+    /// Lets the source know when every acknowledgement has been sent or abandoned, see `SubscriptionHandler::run`
+    _pending: Sender<()>,
+}
+
+impl Ack {
+    pub async fn ack(self) -> Result<()> {
+        if let Err(e) = self.client.ack(&self.publish).await {
+            return Err(AppError::Mqtt(format!("Error acknowledging message: {:?}", e)).into());
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Ack {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Ack")
+            .field("topic", &self.publish.topic)
+            .field("pkid", &self.publish.pkid)
+            .finish()
+    }
 }
 
 pub struct MqttEventSource {
     mqtt_options: MqttOptions,
     subscriptions: Vec<Subscription>,
+    reconnect: MqttReconnectParameters,
+    state: watch::Sender<ConnectionState>,
+    shutdown: Shutdown,
+    metrics: Metrics,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConnectionState {
+    Connecting,
+    Connected,
+    Reconnecting { attempt: u32 },
+    Failed,
 }
 
 pub struct MqttConnectionParameters<'a> {
     pub client_id: &'a str,
     pub host: &'a str,
     pub port: u16,
+    /// Seconds between pings when the connection is idle, must be at least 5
+    pub keep_alive: u16,
+    /// When `false` the broker keeps subscriptions and undelivered QoS 1/2 messages across reconnections
+    pub clean_session: bool,
+    pub credentials: Option<MqttCredentials<'a>>,
+    pub tls: Option<MqttTlsParameters<'a>>,
+    pub reconnect: MqttReconnectParameters,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct MqttReconnectParameters {
+    pub backoff: Backoff,
+    /// Consecutive failed attempts before giving up. `None` retries forever
+    pub max_retries: Option<u32>,
+}
+
+pub struct MqttCredentials<'a> {
+    pub username: &'a str,
+    pub password: &'a str,
 }
 
 impl MqttEventSource {
-    pub fn new(connection: MqttConnectionParameters, subscriptions: Vec<Subscription>) -> Self {
+    pub fn new(connection: MqttConnectionParameters, subscriptions: Vec<Subscription>) -> Result<Self> {
         let mut mqtt_options = MqttOptions::new(connection.client_id, connection.host, connection.port);
-        mqtt_options.set_keep_alive(60);
-        Self {
+        mqtt_options.set_keep_alive(Duration::from_secs(connection.keep_alive.into()));
+        mqtt_options.set_clean_session(connection.clean_session);
+        // Messages are acknowledged once they have been sunk, see `Ack`
+        mqtt_options.set_manual_acks(true);
+        if let Some(credentials) = connection.credentials {
+            mqtt_options.set_credentials(credentials.username, credentials.password);
+        }
+        if let Some(tls) = connection.tls {
+            if tls.insecure_skip_verify {
+                warn!("MQTT TLS certificate verification is disabled");
+            }
+            let config = tls.client_config()?;
+            mqtt_options.set_transport(Transport::tls_with_config(config.into()));
+        }
+        let (state, _) = watch::channel(ConnectionState::Connecting);
+        Ok(Self {
             mqtt_options,
             subscriptions,
-        }
+            reconnect: connection.reconnect,
+            state,
+            shutdown: Shutdown::default(),
+            metrics: Metrics::default(),
+        })
+    }
+
+    /// Once `shutdown` is triggered, the source unsubscribes and closes its channel. It disconnects from the broker
+    /// once the events already sent have been acknowledged or dropped.
+    pub fn with_shutdown(mut self, shutdown: Shutdown) -> Self {
+        self.shutdown = shutdown;
+        self
+    }
+
+    /// Counts the messages received, the payloads that could not be parsed and the reconnections into `metrics`.
+    pub fn with_metrics(mut self, metrics: Metrics) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    /// Returns a handle for observing the broker connection state, it keeps working after `start`.
+    pub fn connection_state(&self) -> watch::Receiver<ConnectionState> {
+        self.state.subscribe()
     }
 }
 
 #[async_trait::async_trait]
 impl EventSource for MqttEventSource {
-    async fn start(self) -> Result<Receiver<Event>> {
-        let event_loop = EventLoop::new(self.mqtt_options, 10);
-        let tx = event_loop.handle();
-        for subscription in self.subscriptions.iter() {
-            let topic = subscription.topic.clone();
-            let subscribe = Subscribe::new(topic.clone(), QoS::AtMostOnce);
-            let request = Request::Subscribe(subscribe);
-            if let Err(e) = tx.send(request).await {
-                return Err(AppError::Mqtt(format!("Error sending Subscribe request: {:?}", e)).into());
-            } else {
-                info!("Subscribed to [{}]", topic);
-            }
-        }
-
+    async fn start(self) -> Result<Receiver<SourceEvent>> {
+        let (client, event_loop) = AsyncClient::new(self.mqtt_options, 10);
         let handler = SubscriptionHandler {
             subscriptions: self.subscriptions,
+            reconnect: self.reconnect,
+            client,
+            state: self.state,
+            shutdown: self.shutdown,
+            metrics: self.metrics,
         };
-        let (chan_tx, chan_rx) = channel::<Event>(10);
+        let (chan_tx, chan_rx) = channel::<SourceEvent>(10);
         tokio::spawn(async move {
             if let Err(e) = handler.run(event_loop, chan_tx).await {
                 error!("Error in SubscriptionHandler: {}", e.to_string());
@@ -63,41 +170,188 @@ impl EventSource for MqttEventSource {
 
 struct SubscriptionHandler {
     subscriptions: Vec<Subscription>,
+    reconnect: MqttReconnectParameters,
+    client: AsyncClient,
+    state: watch::Sender<ConnectionState>,
+    shutdown: Shutdown,
+    metrics: Metrics,
 }
 
 impl SubscriptionHandler {
-    async fn run(&self, mut event_loop: EventLoop, tx: Sender<Event>) -> Result<()> {
+    async fn run(&self, mut event_loop: EventLoop, tx: Sender<SourceEvent>) -> Result<()> {
+        // Every `Ack` holds a clone of `pending_tx`, so `pending_rx` yields `None` once all of them are gone
+        let (pending_tx, mut pending_rx) = channel::<()>(1);
+        let mut channels = Some((tx, pending_tx));
+        let mut attempt = 0;
+        let mut disconnecting = false;
+        let mut subscribing = false;
         loop {
-            match event_loop.poll().await {
-                Ok(v) => {
-                    if let MqttEvent::Incoming(Incoming::Publish(publish)) = v {
-                        if let Err(e) = self.handle_publish(publish, &tx).await {
-                            error!("Error handling publish: {}", e);
+            let stopping = channels.is_none();
+            if subscribing && !stopping {
+                subscribing = !self.subscribe();
+            }
+            let polled = tokio::select! {
+                polled = event_loop.poll() => polled,
+                _ = self.shutdown.triggered(), if !stopping => {
+                    self.unsubscribe();
+                    // The executor drains the events already sent and stops once the channel is closed
+                    channels = None;
+                    continue;
+                }
+                _ = pending_rx.recv(), if stopping && !disconnecting => {
+                    disconnecting = true;
+                    info!("Disconnecting from MQTT broker");
+                    if let Err(e) = self.client.try_disconnect() {
+                        warn!("Error sending Disconnect request: {:?}", e);
+                        return Ok(());
+                    }
+                    continue;
+                }
+            };
+            match polled {
+                Ok(MqttEvent::Outgoing(Outgoing::Disconnect)) => {
+                    info!("Disconnected from MQTT broker");
+                    return Ok(());
+                }
+                Ok(MqttEvent::Incoming(Incoming::ConnAck(_))) if stopping => {}
+                Ok(MqttEvent::Incoming(Incoming::ConnAck(_))) => {
+                    attempt = 0;
+                    info!("Connected to MQTT broker");
+                    self.set_state(ConnectionState::Connected);
+                    subscribing = true;
+                }
+                Ok(MqttEvent::Incoming(Incoming::Publish(publish))) => {
+                    self.metrics.message_received(&publish.topic);
+                    let (tx, pending_tx) = match &channels {
+                        Some(channels) => channels,
+                        // Left unacknowledged, so the broker redelivers it after a restart on a persistent session
+                        None => {
+                            debug!("Ignoring message received while shutting down [topic={}]", publish.topic);
+                            continue;
+                        }
+                    };
+                    if let Err(e) = self.handle_publish(publish, tx, pending_tx).await {
+                        if tx.is_closed() {
+                            info!("Event receiver dropped, stopping SubscriptionHandler");
+                            return Ok(());
                         }
+                        error!("Error handling publish: {}", e);
                     }
                 }
+                Ok(_) => {}
+                Err(e) if stopping => {
+                    warn!("MQTT connection error while shutting down: {:?}", e);
+                    return Ok(());
+                }
                 Err(e) => {
-                    error!("MQTT connection error: {:?}", e);
-                    error!("Finishing program");
-                    std::process::exit(1);
+                    // Subscribing again on the next ConnAck
+                    subscribing = false;
+                    attempt += 1;
+                    if let Some(max_retries) = self.reconnect.max_retries {
+                        if attempt > max_retries {
+                            self.set_state(ConnectionState::Failed);
+                            return Err(AppError::Mqtt(format!("Giving up after {} reconnection attempts: {:?}", max_retries, e)).into());
+                        }
+                    }
+                    let delay = self.reconnect.backoff.delay(attempt);
+                    warn!("MQTT connection error: {:?}. Reconnecting in {:?} [attempt={}]", e, delay, attempt);
+                    self.set_state(ConnectionState::Reconnecting { attempt });
+                    self.metrics.mqtt_reconnecting();
+                    tokio::select! {
+                        _ = tokio::time::sleep(delay) => {}
+                        _ = self.shutdown.triggered() => return Ok(()),
+                    }
                 }
             };
         }
     }
 
-    async fn handle_publish(&self, publish: Publish, tx: &Sender<Event>) -> Result<()> {
-        let subscription = match self.subscriptions.iter().find(|s| s.topic == publish.topic) {
-            Some(s) => s,
+    fn set_state(&self, state: ConnectionState) {
+        self.metrics.set_mqtt_connected(state == ConnectionState::Connected);
+        self.state.send_replace(state);
+    }
+
+    /// Subscriptions are (re)issued on every ConnAck, as a clean session drops them on the broker side.
+    /// Returns `false` when the request could not be queued yet, in which case it is retried after the next poll.
+    fn subscribe(&self) -> bool {
+        let topics = self
+            .subscriptions
+            .iter()
+            .map(|s| SubscribeFilter::new(s.topic.clone(), s.qos()))
+            .collect::<Vec<_>>();
+        // The event loop is not being polled while we are here, so waiting for room in the channel could deadlock.
+        // It fills up with the acks sent by the executor while the broker was away, and each poll drains it.
+        if let Err(e) = self.client.try_subscribe_many(topics) {
+            debug!("Subscribe request not sent yet, retrying after the next poll: {:?}", e);
+            return false;
+        }
+        for subscription in self.subscriptions.iter() {
+            info!("Subscribed to [{}] [qos={}]", subscription.topic, subscription.qos);
+        }
+        true
+    }
+
+    /// Stops receiving messages while the pending ones are acknowledged.
+    fn unsubscribe(&self) {
+        for subscription in self.subscriptions.iter() {
+            match self.client.try_unsubscribe(subscription.topic.clone()) {
+                Ok(_) => info!("Unsubscribed from [{}]", subscription.topic),
+                Err(e) => warn!("Error sending Unsubscribe request for [{}]: {:?}", subscription.topic, e),
+            }
+        }
+    }
+
+    /// Acknowledges messages that will never reach a sink, so the broker does not redeliver them.
+    fn discard(&self, publish: &Publish) {
+        if let Err(e) = self.client.try_ack(publish) {
+            warn!("Error acknowledging discarded message [topic={}]: {:?}", publish.topic, e);
+        }
+    }
+
+    async fn handle_publish(&self, publish: Publish, tx: &Sender<SourceEvent>, pending_tx: &Sender<()>) -> Result<()> {
+        let received_at = Utc::now();
+        let matched = self
+            .subscriptions
+            .iter()
+            .find_map(|s| s.device_name_for(&publish.topic).map(|device_name| (s, device_name)));
+        let (subscription, device_name) = match matched {
+            Some(matched) => matched,
             None => {
                 trace!("Received event for unknown subscription [topic={}]", publish.topic);
+                self.discard(&publish);
                 return Ok(());
             }
         };
 
-        let event: RawMqttEvent = serde_json::from_slice(&publish.payload)?;
-        let converted = Event::from_mqtt(event, &subscription.device_name);
+        let payload = match serde_json::from_slice::<serde_json::Value>(&publish.payload) {
+            Ok(serde_json::Value::Object(payload)) => payload,
+            Ok(_) => {
+                self.metrics.parse_failed(&publish.topic);
+                self.discard(&publish);
+                return Err(AppError::Payload(format!("Payload for topic [{}] is not a JSON object", publish.topic)).into());
+            }
+            Err(e) => {
+                self.metrics.parse_failed(&publish.topic);
+                self.discard(&publish);
+                return Err(e.into());
+            }
+        };
+        let converted = Event::from_mqtt(&payload, &device_name, subscription, received_at).with_topic(&publish.topic);
         trace!("Received event: {:?}", converted);
-        tx.send(converted).await?;
+        let ack = match publish.qos {
+            QoS::AtMostOnce => None,
+            _ => Some(Ack {
+                client: self.client.clone(),
+                publish,
+                _pending: pending_tx.clone(),
+            }),
+        };
+        // Counted before sending, so the executor never dequeues an event that has not been counted yet
+        self.metrics.event_queued();
+        if let Err(e) = tx.send(SourceEvent { event: converted, ack }).await {
+            self.metrics.event_dequeued();
+            return Err(e.into());
+        }
         Ok(())
     }
 }
@@ -1,5 +1,9 @@
 pub mod event_source;
+pub mod monitor;
+pub mod mqtt_tls;
 pub mod sink;
 
 pub use event_source::*;
+pub use monitor::*;
+pub use mqtt_tls::*;
 pub use sink::*;
@@ -0,0 +1,179 @@
+use crate::{Event, EventSink, FieldValue, Shutdown, Subscription};
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+
+/// Measurement of the events emitted when a device goes offline.
+pub const STATUS_MEASUREMENT: &str = "device_status";
+
+/// Time between two checks of the monitored devices.
+pub const SWEEP_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DeviceStatus {
+    Online,
+    /// Silent for longer than `max_silence_ms`
+    Stale,
+    /// Silent for longer than twice `max_silence_ms`
+    Offline,
+}
+
+impl DeviceStatus {
+    /// Status of a device that has been silent for `silence`.
+    pub fn after(silence: chrono::Duration, max_silence: chrono::Duration) -> Self {
+        if silence > max_silence * 2 {
+            DeviceStatus::Offline
+        } else if silence > max_silence {
+            DeviceStatus::Stale
+        } else {
+            DeviceStatus::Online
+        }
+    }
+}
+
+/// Status of the monitored devices, updated by a `DeviceMonitor`. Clones share the same statuses.
+#[derive(Clone, Default)]
+pub struct DeviceStatuses {
+    statuses: Arc<RwLock<HashMap<String, DeviceStatus>>>,
+}
+
+impl DeviceStatuses {
+    /// `None` when the device is not monitored or nothing was received from it.
+    pub fn get(&self, device_name: &str) -> Option<DeviceStatus> {
+        self.statuses.read().expect("Statuses lock poisoned").get(device_name).copied()
+    }
+
+    /// Sets the status of the device, returning the previous one.
+    fn set(&self, device_name: &str, status: DeviceStatus) -> Option<DeviceStatus> {
+        self.statuses
+            .write()
+            .expect("Statuses lock poisoned")
+            .insert(device_name.to_string(), status)
+    }
+}
+
+struct Watched {
+    /// Index of the subscription the device was received on
+    subscription: usize,
+    topic: String,
+    last_seen: DateTime<Utc>,
+}
+
+/// Sink that keeps track of when each device was last seen before passing its events to the inner sink.
+/// Devices received on a subscription with `max_silence_ms` are checked by `run`, which sends an event
+/// with a `status` field into `STATUS_MEASUREMENT` to the inner sink when one goes offline.
+pub struct DeviceMonitor {
+    subscriptions: Vec<Subscription>,
+    inner: Arc<dyn EventSink>,
+    statuses: DeviceStatuses,
+    devices: Mutex<HashMap<String, Watched>>,
+}
+
+impl DeviceMonitor {
+    pub fn new(subscriptions: Vec<Subscription>, inner: Arc<dyn EventSink>, statuses: DeviceStatuses) -> Self {
+        Self {
+            subscriptions,
+            inner,
+            statuses,
+            devices: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn max_silence(&self, subscription: usize) -> chrono::Duration {
+        let millis = self.subscriptions[subscription].max_silence_ms.unwrap_or_default();
+        chrono::Duration::milliseconds(millis as i64)
+    }
+
+    fn observe(&self, event: &Event) {
+        let topic = match &event.topic {
+            Some(topic) => topic,
+            None => return,
+        };
+        // The first matching subscription is the one the source used for the event
+        let subscription = self.subscriptions.iter().position(|s| s.device_name_for(topic).is_some());
+        let subscription = match subscription {
+            Some(subscription) if self.subscriptions[subscription].max_silence_ms.is_some() => subscription,
+            _ => return,
+        };
+
+        self.devices.lock().expect("Devices lock poisoned").insert(
+            event.device_name.clone(),
+            Watched {
+                subscription,
+                topic: topic.clone(),
+                last_seen: event.received_at,
+            },
+        );
+        match self.statuses.set(&event.device_name, DeviceStatus::Online) {
+            Some(DeviceStatus::Stale) | Some(DeviceStatus::Offline) => info!("Device [{}] is back online", event.device_name),
+            _ => {}
+        }
+    }
+
+    /// Updates the status of the devices as of `now`, returning the events of those that just went offline.
+    pub fn sweep_at(&self, now: DateTime<Utc>) -> Vec<Event> {
+        let devices = self.devices.lock().expect("Devices lock poisoned");
+        let mut offline = Vec::new();
+        for (device_name, watched) in devices.iter() {
+            let status = DeviceStatus::after(now - watched.last_seen, self.max_silence(watched.subscription));
+            let previous = self.statuses.set(device_name, status);
+            if previous == Some(status) {
+                continue;
+            }
+            warn!(
+                "Device [{}] is {:?} [last_seen={}]",
+                device_name,
+                status,
+                watched.last_seen.to_rfc3339()
+            );
+            if status == DeviceStatus::Offline {
+                let subscription = &self.subscriptions[watched.subscription];
+                let mut event = Event::new(device_name)
+                    .with_topic(&watched.topic)
+                    .with_received_at(now)
+                    .with_measurement(STATUS_MEASUREMENT)
+                    .with_field("status", FieldValue::String("offline".to_string()));
+                event.tags = subscription.tags.clone();
+                event.retention_policy = subscription.retention_policy.clone();
+                offline.push(event);
+            }
+        }
+        offline
+    }
+
+    /// Checks the devices every `SWEEP_INTERVAL` until `shutdown` is triggered.
+    pub async fn run(self: Arc<Self>, shutdown: Shutdown) {
+        let mut interval = tokio::time::interval(SWEEP_INTERVAL);
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {}
+                _ = shutdown.triggered() => return,
+            }
+            for event in self.sweep_at(Utc::now()) {
+                let device_name = event.device_name.clone();
+                if let Err(e) = self.inner.sink(event).await {
+                    error!("Error sinking offline event [device={}]: {}", device_name, e);
+                }
+            }
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl EventSink for DeviceMonitor {
+    async fn sink(&self, event: Event) -> Result<()> {
+        self.observe(&event);
+        self.inner.sink(event).await
+    }
+
+    async fn flush(&self) -> Result<()> {
+        self.inner.flush().await
+    }
+
+    async fn close(&self) -> Result<()> {
+        self.inner.close().await
+    }
+}
@@ -0,0 +1,108 @@
+use crate::AppError;
+use anyhow::{Context, Result};
+use rustls::client::{ServerCertVerified, ServerCertVerifier};
+use rustls::{Certificate, ClientConfig, Error as TlsError, OwnedTrustAnchor, PrivateKey, RootCertStore, ServerName};
+use rustls_pemfile::{certs, read_all, Item};
+use std::io::{BufReader, Cursor};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+pub struct MqttTlsParameters<'a> {
+    pub ca_file: Option<&'a str>,
+    pub client_auth: Option<MqttClientAuth<'a>>,
+    pub insecure_skip_verify: bool,
+}
+
+pub struct MqttClientAuth<'a> {
+    pub cert_file: &'a str,
+    pub key_file: &'a str,
+}
+
+impl MqttTlsParameters<'_> {
+    /// Loads every configured file so broken certificates are reported before connecting.
+    pub fn validate(&self) -> Result<()> {
+        self.client_config().map(|_| ())
+    }
+
+    pub(crate) fn client_config(&self) -> Result<ClientConfig> {
+        let mut roots = RootCertStore::empty();
+        match self.ca_file {
+            Some(ca_file) => {
+                let ca = read_file(ca_file)?;
+                let ca_certs = certs(&mut BufReader::new(Cursor::new(ca)))
+                    .map_err(|_| AppError::Tls(format!("Could not parse CA file [{}]", ca_file)))?;
+                let (added, _) = roots.add_parsable_certificates(&ca_certs);
+                if added == 0 {
+                    return Err(AppError::Tls(format!("No valid certificates found in CA file [{}]", ca_file)).into());
+                }
+            }
+            None => roots.add_server_trust_anchors(
+                webpki_roots::TLS_SERVER_ROOTS
+                    .0
+                    .iter()
+                    .map(|ta| OwnedTrustAnchor::from_subject_spki_name_constraints(ta.subject, ta.spki, ta.name_constraints)),
+            ),
+        }
+
+        let builder = ClientConfig::builder().with_safe_defaults().with_root_certificates(roots);
+        let mut config = match &self.client_auth {
+            Some(client_auth) => {
+                let cert = read_file(client_auth.cert_file)?;
+                let cert_chain: Vec<Certificate> = certs(&mut BufReader::new(Cursor::new(cert)))
+                    .map_err(|_| AppError::Tls(format!("Could not parse client certificate [{}]", client_auth.cert_file)))?
+                    .into_iter()
+                    .map(Certificate)
+                    .collect();
+                if cert_chain.is_empty() {
+                    return Err(AppError::Tls(format!("No certificates found in [{}]", client_auth.cert_file)).into());
+                }
+
+                let key = read_file(client_auth.key_file)?;
+                let key = read_all(&mut BufReader::new(Cursor::new(key)))
+                    .unwrap_or_default()
+                    .into_iter()
+                    .find_map(|item| match item {
+                        Item::PKCS8Key(key) | Item::RSAKey(key) | Item::ECKey(key) => Some(key),
+                        _ => None,
+                    })
+                    .ok_or_else(|| {
+                        AppError::Tls(format!(
+                            "No private key found in [{}], expected a PEM PKCS#8 (PRIVATE KEY), RSA (RSA PRIVATE KEY) or SEC1 (EC PRIVATE KEY) key",
+                            client_auth.key_file
+                        ))
+                    })?;
+
+                builder
+                    .with_single_cert(cert_chain, PrivateKey(key))
+                    .map_err(|e| AppError::Tls(format!("Invalid client certificate or key: {}", e)))?
+            }
+            None => builder.with_no_client_auth(),
+        };
+
+        if self.insecure_skip_verify {
+            config.dangerous().set_certificate_verifier(Arc::new(NoCertificateVerification));
+        }
+
+        Ok(config)
+    }
+}
+
+fn read_file(path: &str) -> Result<Vec<u8>> {
+    std::fs::read(path).with_context(|| format!("Error reading file [{}]", path))
+}
+
+struct NoCertificateVerification;
+
+impl ServerCertVerifier for NoCertificateVerification {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &Certificate,
+        _intermediates: &[Certificate],
+        _server_name: &ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: SystemTime,
+    ) -> Result<ServerCertVerified, TlsError> {
+        Ok(ServerCertVerified::assertion())
+    }
+}
@@ -0,0 +1,120 @@
+use super::EventSink;
+use crate::topic::glob_match;
+use crate::{Event, Metrics};
+use anyhow::Result;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// Conditions on an event. Every non-empty condition must hold, and a condition holds when any of its values does.
+/// Device names, topics and tag values are globs, see `topic::glob_match`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct EventMatcher {
+    #[serde(default)]
+    pub devices: Vec<String>,
+    /// Topics the events were received on. Events without a topic never match
+    #[serde(default)]
+    pub topics: Vec<String>,
+    /// Tags the event must have, with the value they must match
+    #[serde(default)]
+    pub tags: BTreeMap<String, String>,
+    /// Fields, one of which the event must have
+    #[serde(default)]
+    pub fields: Vec<String>,
+}
+
+impl EventMatcher {
+    pub fn matches(&self, event: &Event) -> bool {
+        let device = self.devices.is_empty() || self.devices.iter().any(|d| glob_match(d, &event.device_name));
+        let topic = self.topics.is_empty()
+            || event
+                .topic
+                .as_ref()
+                .map(|topic| self.topics.iter().any(|t| glob_match(t, topic)))
+                .unwrap_or(false);
+        let tags = self
+            .tags
+            .iter()
+            .all(|(name, value)| event.tags.get(name).map(|v| glob_match(value, v)).unwrap_or(false));
+        let fields = self.fields.is_empty() || self.fields.iter().any(|f| event.fields.contains_key(f));
+        device && topic && tags && fields
+    }
+}
+
+/// Which events reach a sink: the ones matching `include`, if set, and not matching `exclude`, if set.
+#[derive(Clone, Debug, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct EventFilter {
+    pub include: Option<EventMatcher>,
+    pub exclude: Option<EventMatcher>,
+}
+
+impl EventFilter {
+    pub fn accepts(&self, event: &Event) -> bool {
+        self.include.as_ref().map(|m| m.matches(event)).unwrap_or(true) && !self.exclude.as_ref().map(|m| m.matches(event)).unwrap_or(false)
+    }
+}
+
+/// Sink that only passes the events accepted by its filter to the inner sink. The other ones succeed without
+/// being stored, and are counted.
+pub struct FilterSink {
+    name: String,
+    inner: Arc<dyn EventSink>,
+    filter: EventFilter,
+    skipped: AtomicU64,
+    metrics: Metrics,
+}
+
+impl FilterSink {
+    pub fn new(name: &str, inner: Arc<dyn EventSink>, filter: EventFilter) -> Self {
+        Self {
+            name: name.to_string(),
+            inner,
+            filter,
+            skipped: AtomicU64::new(0),
+            metrics: Metrics::default(),
+        }
+    }
+
+    /// Also counts the skipped events into `metrics`, under the name of the sink.
+    pub fn with_metrics(mut self, metrics: Metrics) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Events that did not pass the filter.
+    pub fn skipped(&self) -> u64 {
+        self.skipped.load(Ordering::Relaxed)
+    }
+}
+
+#[async_trait::async_trait]
+impl EventSink for FilterSink {
+    async fn sink(&self, event: Event) -> Result<()> {
+        if self.filter.accepts(&event) {
+            return self.inner.sink(event).await;
+        }
+        let skipped = self.skipped.fetch_add(1, Ordering::Relaxed) + 1;
+        self.metrics.event_filtered(&self.name);
+        trace!(
+            "Event filtered out of [{}] [device={}] [skipped={}]",
+            self.name,
+            event.device_name,
+            skipped
+        );
+        Ok(())
+    }
+
+    async fn flush(&self) -> Result<()> {
+        self.inner.flush().await
+    }
+
+    async fn close(&self) -> Result<()> {
+        self.inner.close().await
+    }
+}
@@ -0,0 +1,233 @@
+use super::buffer::DiskBuffer;
+use super::client::InfluxDbClient;
+use crate::{AppError, RetryParameters, SinkError};
+use anyhow::Result;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Weak};
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+#[derive(Clone, Copy, Debug)]
+pub struct InfluxDbBatchParameters {
+    /// Points gathered before writing them in a single request. 1 writes every point as it arrives
+    pub batch_size: usize,
+    /// Maximum time a point waits in a partial batch
+    pub flush_interval: Duration,
+}
+
+impl Default for InfluxDbBatchParameters {
+    fn default() -> Self {
+        Self {
+            batch_size: 1,
+            flush_interval: Duration::from_secs(1),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum FlushReason {
+    Size,
+    Interval,
+    Requested,
+    Shutdown,
+}
+
+#[derive(Default)]
+struct Pending {
+    /// Points with the retention policy they are written into
+    points: Vec<(Option<String>, String)>,
+    oldest: Option<Instant>,
+}
+
+impl Pending {
+    /// Lines grouped by retention policy, as each policy needs its own request. Groups keep the order of their first point.
+    fn into_batches(self) -> Vec<(Option<String>, Vec<String>)> {
+        let mut batches: Vec<(Option<String>, Vec<String>)> = Vec::new();
+        for (retention_policy, line) in self.points {
+            match batches.iter_mut().find(|(rp, _)| *rp == retention_policy) {
+                Some((_, lines)) => lines.push(line),
+                None => batches.push((retention_policy, vec![line])),
+            }
+        }
+        batches
+    }
+}
+
+/// Gathers line protocol points and writes them once `batch_size` is reached or `flush_interval` has elapsed.
+/// Failed writes are retried as a whole batch, as retrying the point that filled it would only start a new one.
+pub(crate) struct Batcher {
+    client: InfluxDbClient,
+    params: InfluxDbBatchParameters,
+    retry: RetryParameters,
+    buffer: Option<DiskBuffer>,
+    pending: Mutex<Pending>,
+    flushes: AtomicU64,
+}
+
+impl Batcher {
+    pub fn new(client: InfluxDbClient, params: InfluxDbBatchParameters, retry: RetryParameters, buffer: Option<DiskBuffer>) -> Arc<Self> {
+        let replay_interval = buffer.as_ref().map(|b| b.replay_interval);
+        let batcher = Arc::new(Self {
+            client,
+            params,
+            retry,
+            buffer,
+            pending: Mutex::new(Pending::default()),
+            flushes: AtomicU64::new(0),
+        });
+        if params.batch_size > 1 {
+            tokio::spawn(Self::flush_periodically(Arc::downgrade(&batcher), params.flush_interval));
+        }
+        if let Some(replay_interval) = replay_interval {
+            tokio::spawn(Self::replay_periodically(Arc::downgrade(&batcher), replay_interval));
+        }
+        batcher
+    }
+
+    /// Replays the buffered batches once InfluxDB answers the health check again.
+    async fn replay_periodically(batcher: Weak<Self>, replay_interval: Duration) {
+        let mut interval = tokio::time::interval(replay_interval);
+        loop {
+            interval.tick().await;
+            let batcher = match batcher.upgrade() {
+                Some(batcher) => batcher,
+                None => return,
+            };
+            let buffer = match &batcher.buffer {
+                Some(buffer) => buffer,
+                None => return,
+            };
+            if buffer.is_empty().await {
+                continue;
+            }
+            if let Err(e) = batcher.client.ping().await {
+                debug!("InfluxDb still unavailable, keeping buffered points: {}", e);
+                continue;
+            }
+            match buffer.replay(&batcher.client).await {
+                Ok(batches) => info!("Replayed buffered points into InfluxDb [batches={}]", batches),
+                Err(e) => warn!("Error replaying buffered points into InfluxDb: {}", e),
+            }
+        }
+    }
+
+    /// The timer stops once the sink owning the batcher is dropped.
+    async fn flush_periodically(batcher: Weak<Self>, flush_interval: Duration) {
+        let mut interval = tokio::time::interval(flush_interval);
+        loop {
+            interval.tick().await;
+            let batcher = match batcher.upgrade() {
+                Some(batcher) => batcher,
+                None => return,
+            };
+            if let Err(e) = batcher.flush(FlushReason::Interval).await {
+                error!("Error flushing batch to InfluxDb: {}", e);
+            }
+        }
+    }
+
+    /// Adds a point, writing the batch when it is full.
+    pub async fn push(&self, line: String, retention_policy: Option<String>) -> Result<()> {
+        let lines = {
+            let mut pending = self.pending.lock().await;
+            pending.oldest.get_or_insert_with(Instant::now);
+            pending.points.push((retention_policy, line));
+            if pending.points.len() < self.params.batch_size {
+                return Ok(());
+            }
+            std::mem::take(&mut *pending)
+        };
+        self.write(lines, FlushReason::Size).await
+    }
+
+    pub async fn flush(&self, reason: FlushReason) -> Result<()> {
+        let lines = std::mem::take(&mut *self.pending.lock().await);
+        if lines.points.is_empty() {
+            return Ok(());
+        }
+        self.write(lines, reason).await
+    }
+
+    async fn write(&self, pending: Pending, reason: FlushReason) -> Result<()> {
+        let age = pending.oldest.map(|oldest| oldest.elapsed()).unwrap_or_default();
+        let mut res = Ok(());
+        for (retention_policy, lines) in pending.into_batches() {
+            let batch_res = self.write_retention_policy(lines, retention_policy.as_deref(), reason, age).await;
+            // Every batch is attempted, the first error is reported
+            if res.is_ok() {
+                res = batch_res;
+            }
+        }
+        res
+    }
+
+    async fn write_retention_policy(
+        &self,
+        lines: Vec<String>,
+        retention_policy: Option<&str>,
+        reason: FlushReason,
+        age: Duration,
+    ) -> Result<()> {
+        let points = lines.len();
+        let lines = lines.join("\n");
+        let buffer = match &self.buffer {
+            Some(buffer) => buffer,
+            None => return self.write_batch(lines, retention_policy, points, reason, age).await,
+        };
+
+        // While there are buffered points InfluxDB is considered down, new points queue behind them to keep the order
+        if !buffer.is_empty().await {
+            buffer.push(&lines, retention_policy).await?;
+            debug!("Batch buffered behind previous points [points={}]", points);
+            return Ok(());
+        }
+        match self.write_batch(lines.clone(), retention_policy, points, reason, age).await {
+            Err(e) if SinkError::find(&e).map(SinkError::is_retryable).unwrap_or(false) => {
+                warn!("Error writing batch to InfluxDb, buffering it [points={}]: {}", points, e);
+                buffer.push(&lines, retention_policy).await.map_err(|buffer_error| {
+                    error!("Error buffering batch: {}", buffer_error);
+                    e
+                })
+            }
+            res => res,
+        }
+    }
+
+    async fn write_batch(
+        &self,
+        lines: String,
+        retention_policy: Option<&str>,
+        points: usize,
+        reason: FlushReason,
+        age: Duration,
+    ) -> Result<()> {
+        let start = Instant::now();
+        let mut attempt = 1;
+        loop {
+            let e = match self.client.write(lines.clone(), retention_policy).await {
+                Ok(_) => break,
+                Err(e) => anyhow::Error::from(AppError::Sink(e)),
+            };
+            if attempt >= self.retry.max_attempts || !(self.retry.classifier)(&e) {
+                return Err(e);
+            }
+            let delay = self.retry.backoff.delay(attempt);
+            warn!(
+                "Error writing batch to InfluxDb: {}. Retrying in {:?} [points={}] [attempt={}/{}]",
+                e, delay, points, attempt, self.retry.max_attempts
+            );
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+        let flushes = self.flushes.fetch_add(1, Ordering::Relaxed) + 1;
+        info!(
+            "Batch stored into InfluxDb [points={}] [reason={:?}] [write_ms={}] [oldest_ms={}] [flushes={}]",
+            points,
+            reason,
+            start.elapsed().as_millis(),
+            age.as_millis(),
+            flushes
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,191 @@
+use super::client::InfluxDbClient;
+use crate::AppError;
+use anyhow::{Context, Result};
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::sync::Mutex;
+
+const EXTENSION: &str = "lp";
+/// Extension of the batches being written, see `DiskBuffer::push`
+const TMP_EXTENSION: &str = "tmp";
+
+pub struct InfluxDbBufferParameters<'a> {
+    /// Directory holding the points that could not be written
+    pub dir: &'a str,
+    /// Maximum size of the buffered points, the oldest ones are evicted once it is reached
+    pub max_bytes: u64,
+    /// Time between checks for InfluxDB being back
+    pub replay_interval: Duration,
+}
+
+struct Segment {
+    seq: u64,
+    retention_policy: Option<String>,
+    size: u64,
+}
+
+struct BufferState {
+    segments: VecDeque<Segment>,
+    total_bytes: u64,
+    next_seq: u64,
+}
+
+/// Disk queue of line protocol batches. Every batch is stored in its own file, named after an increasing
+/// sequence number so the order is kept across restarts, followed by the retention policy of the batch if it has one.
+pub(crate) struct DiskBuffer {
+    dir: PathBuf,
+    max_bytes: u64,
+    pub(crate) replay_interval: Duration,
+    state: Mutex<BufferState>,
+}
+
+impl DiskBuffer {
+    pub fn open(params: &InfluxDbBufferParameters) -> Result<Self> {
+        let dir = PathBuf::from(params.dir);
+        std::fs::create_dir_all(&dir).with_context(|| format!("Error creating buffer dir [{}]", dir.display()))?;
+
+        let mut segments = Vec::new();
+        for entry in std::fs::read_dir(&dir).with_context(|| format!("Error reading buffer dir [{}]", dir.display()))? {
+            let entry = entry?;
+            let path = entry.path();
+            let extension = path.extension().and_then(|e| e.to_str());
+            if extension == Some(TMP_EXTENSION) {
+                // Left by a crash in the middle of `push`, the batch was never acknowledged as buffered
+                warn!("Removing partially written batch [{}]", path.display());
+                std::fs::remove_file(&path).with_context(|| format!("Error removing [{}]", path.display()))?;
+                continue;
+            }
+            if extension != Some(EXTENSION) {
+                continue;
+            }
+            match path.file_stem().and_then(|s| s.to_str()).and_then(Self::parse_stem) {
+                Some((seq, retention_policy)) => segments.push(Segment {
+                    seq,
+                    retention_policy,
+                    size: entry.metadata()?.len(),
+                }),
+                None => warn!("Ignoring unknown file in buffer dir [{}]", path.display()),
+            }
+        }
+        segments.sort_by_key(|s| s.seq);
+
+        let total_bytes = segments.iter().map(|s| s.size).sum();
+        let next_seq = segments.last().map(|s| s.seq + 1).unwrap_or(0);
+        if !segments.is_empty() {
+            info!(
+                "Found buffered InfluxDb points [dir={}] [batches={}] [bytes={}]",
+                dir.display(),
+                segments.len(),
+                total_bytes
+            );
+        }
+
+        Ok(Self {
+            dir,
+            max_bytes: params.max_bytes,
+            replay_interval: params.replay_interval,
+            state: Mutex::new(BufferState {
+                segments: segments.into(),
+                total_bytes,
+                next_seq,
+            }),
+        })
+    }
+
+    fn path(dir: &Path, segment: &Segment) -> PathBuf {
+        match &segment.retention_policy {
+            Some(retention_policy) => dir.join(format!("{:020}.{}.{}", segment.seq, retention_policy, EXTENSION)),
+            None => dir.join(format!("{:020}.{}", segment.seq, EXTENSION)),
+        }
+    }
+
+    fn parse_stem(stem: &str) -> Option<(u64, Option<String>)> {
+        match stem.split_once('.') {
+            Some((seq, retention_policy)) => Some((seq.parse().ok()?, Some(retention_policy.to_string()))),
+            None => Some((stem.parse().ok()?, None)),
+        }
+    }
+
+    pub async fn is_empty(&self) -> bool {
+        self.state.lock().await.segments.is_empty()
+    }
+
+    /// Stores a batch, evicting the oldest ones when there is no room for it.
+    pub async fn push(&self, lines: &str, retention_policy: Option<&str>) -> Result<()> {
+        let size = lines.len() as u64;
+        if size > self.max_bytes {
+            return Err(AppError::Influx(format!("Batch of {} bytes does not fit in the buffer", size)).into());
+        }
+
+        let mut state = self.state.lock().await;
+        while state.total_bytes + size > self.max_bytes {
+            let oldest = match state.segments.pop_front() {
+                Some(oldest) => oldest,
+                None => break,
+            };
+            state.total_bytes -= oldest.size;
+            warn!("InfluxDb buffer is full, evicting oldest batch [bytes={}]", oldest.size);
+            if let Err(e) = tokio::fs::remove_file(Self::path(&self.dir, &oldest)).await {
+                error!("Error removing evicted batch: {}", e);
+            }
+        }
+
+        let segment = Segment {
+            seq: state.next_seq,
+            retention_policy: retention_policy.map(str::to_string),
+            size,
+        };
+        // Write and rename, so a crash never leaves a partial batch behind
+        let tmp = self.dir.join(format!("{:020}.{}", segment.seq, TMP_EXTENSION));
+        tokio::fs::write(&tmp, lines).await.context("Error writing buffered batch")?;
+        tokio::fs::rename(&tmp, Self::path(&self.dir, &segment))
+            .await
+            .context("Error writing buffered batch")?;
+        state.next_seq += 1;
+        state.total_bytes += size;
+        state.segments.push_back(segment);
+        Ok(())
+    }
+
+    /// Writes the buffered batches oldest first, stopping at the first one that fails with a retryable error.
+    /// Returns the number of batches written.
+    pub async fn replay(&self, client: &InfluxDbClient) -> Result<usize> {
+        let mut written = 0;
+        loop {
+            let (seq, retention_policy, path) = match self.state.lock().await.segments.front() {
+                Some(segment) => (segment.seq, segment.retention_policy.clone(), Self::path(&self.dir, segment)),
+                None => return Ok(written),
+            };
+            let lines = match tokio::fs::read_to_string(&path).await {
+                Ok(lines) => Some(lines),
+                Err(e) => {
+                    error!("Error reading buffered batch [{}], dropping it: {}", path.display(), e);
+                    None
+                }
+            };
+            if let Some(lines) = lines {
+                match client.write(lines, retention_policy.as_deref()).await {
+                    Ok(_) => written += 1,
+                    Err(e) if e.is_retryable() => return Err(AppError::Sink(e).into()),
+                    Err(e) => error!("InfluxDb rejected buffered batch [{}], dropping it: {}", path.display(), e),
+                }
+            }
+            self.remove(seq).await;
+        }
+    }
+
+    /// Removes a batch unless it has already been evicted.
+    async fn remove(&self, seq: u64) {
+        let mut state = self.state.lock().await;
+        if state.segments.front().map(|s| s.seq) != Some(seq) {
+            return;
+        }
+        if let Some(segment) = state.segments.pop_front() {
+            state.total_bytes -= segment.size;
+            if let Err(e) = tokio::fs::remove_file(Self::path(&self.dir, &segment)).await {
+                error!("Error removing buffered batch: {}", e);
+            }
+        }
+    }
+}
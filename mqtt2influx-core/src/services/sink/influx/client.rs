@@ -0,0 +1,147 @@
+use super::{InfluxDbApi, InfluxDbCredentials};
+use crate::{AppError, SinkError};
+use anyhow::{Context, Result};
+use reqwest::header::{HeaderMap, AUTHORIZATION, CONTENT_TYPE};
+use reqwest::{Client, RequestBuilder, Response, Url};
+
+/// Precision of the timestamps sent in the line protocol
+const PRECISION: &str = "ns";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InfluxDbHealth {
+    pub build_type: String,
+    pub version: String,
+}
+
+enum Auth {
+    Basic(String, String),
+    Token(String),
+}
+
+/// HTTP client for the InfluxDB write and health endpoints of both the v1 and v2 APIs.
+pub(crate) struct InfluxDbClient {
+    client: Client,
+    server: String,
+    write_url: Url,
+    auth: Option<Auth>,
+    v2: bool,
+}
+
+impl InfluxDbClient {
+    pub fn new(server: &str, api: &InfluxDbApi) -> Result<Self> {
+        let server = server.trim_end_matches('/').to_string();
+        let (write_url, auth, v2) = match api {
+            InfluxDbApi::V1 { db, credentials } => {
+                let url = Url::parse_with_params(&format!("{}/write", server), &[("db", *db), ("precision", PRECISION)]);
+                let auth = credentials
+                    .as_ref()
+                    .map(|InfluxDbCredentials { username, password }| Auth::Basic(username.to_string(), password.to_string()));
+                (url, auth, false)
+            }
+            InfluxDbApi::V2 { org, bucket, token } => {
+                let url = Url::parse_with_params(
+                    &format!("{}/api/v2/write", server),
+                    &[("org", *org), ("bucket", *bucket), ("precision", PRECISION)],
+                );
+                (url, Some(Auth::Token(token.to_string())), true)
+            }
+        };
+        let write_url = write_url.with_context(|| format!("Invalid InfluxDB server [{}]", server))?;
+        Ok(Self {
+            client: Client::new(),
+            server,
+            write_url,
+            auth,
+            v2,
+        })
+    }
+
+    /// Checks that the server is up, using `/ping` on v1 and `/health` on v2.
+    pub async fn ping(&self) -> Result<InfluxDbHealth> {
+        if self.v2 {
+            self.health().await
+        } else {
+            self.ping_v1().await
+        }
+    }
+
+    async fn ping_v1(&self) -> Result<InfluxDbHealth> {
+        let res = self
+            .client
+            .get(format!("{}/ping", self.server))
+            .send()
+            .await
+            .map_err(|e| AppError::Sink(network_error(e)))?;
+        if !res.status().is_success() {
+            return Err(AppError::Sink(status_error(res).await).into());
+        }
+        let headers = res.headers();
+        Ok(InfluxDbHealth {
+            build_type: header_value(headers, "X-Influxdb-Build"),
+            version: header_value(headers, "X-Influxdb-Version"),
+        })
+    }
+
+    /// InfluxDB v2 answers `/health` with a JSON body, while v3 answers in plain text and requires the token, so any
+    /// successful status is healthy and the version is read from the body when it is JSON.
+    async fn health(&self) -> Result<InfluxDbHealth> {
+        #[derive(serde::Deserialize)]
+        struct Health {
+            #[serde(default)]
+            version: Option<String>,
+        }
+
+        let req = self.client.get(format!("{}/health", self.server));
+        let res = self.authorize(req).send().await.map_err(|e| AppError::Sink(network_error(e)))?;
+        if !res.status().is_success() {
+            return Err(AppError::Sink(status_error(res).await).into());
+        }
+        let header_version = header_value(res.headers(), "X-Influxdb-Version");
+        let body = res.text().await.unwrap_or_default();
+        let version = serde_json::from_str::<Health>(&body)
+            .ok()
+            .and_then(|health| health.version)
+            .unwrap_or(header_version);
+        Ok(InfluxDbHealth {
+            build_type: "v2".to_string(),
+            version,
+        })
+    }
+
+    fn authorize(&self, req: RequestBuilder) -> RequestBuilder {
+        match &self.auth {
+            Some(Auth::Basic(username, password)) => req.basic_auth(username, Some(password)),
+            Some(Auth::Token(token)) => req.header(AUTHORIZATION, format!("Token {}", token)),
+            None => req,
+        }
+    }
+
+    /// Writes newline separated points in line protocol. The retention policy only applies to the v1 API,
+    /// v2 buckets have a single one.
+    pub async fn write(&self, lines: String, retention_policy: Option<&str>) -> Result<(), SinkError> {
+        let mut url = self.write_url.clone();
+        if let (false, Some(retention_policy)) = (self.v2, retention_policy) {
+            url.query_pairs_mut().append_pair("rp", retention_policy);
+        }
+        let req = self.client.post(url).header(CONTENT_TYPE, "text/plain; charset=utf-8").body(lines);
+        let res = self.authorize(req).send().await.map_err(network_error)?;
+        if !res.status().is_success() {
+            return Err(status_error(res).await);
+        }
+        Ok(())
+    }
+}
+
+fn network_error(e: reqwest::Error) -> SinkError {
+    SinkError::Network(e.to_string())
+}
+
+async fn status_error(res: Response) -> SinkError {
+    let status = res.status().as_u16();
+    let body = res.text().await.unwrap_or_default();
+    SinkError::from_status(status, body)
+}
+
+fn header_value(headers: &HeaderMap, name: &str) -> String {
+    headers.get(name).and_then(|v| v.to_str().ok()).unwrap_or("unknown").to_string()
+}
@@ -0,0 +1,142 @@
+use super::EventSink;
+use crate::{AppError, Event, FieldValue, RetryParameters};
+use anyhow::Result;
+use batch::{Batcher, FlushReason};
+use buffer::DiskBuffer;
+use client::InfluxDbClient;
+use influxdb::{InfluxDbWriteable, Query, Timestamp, WriteQuery};
+use std::sync::Arc;
+
+pub use batch::InfluxDbBatchParameters;
+pub use buffer::InfluxDbBufferParameters;
+pub use client::InfluxDbHealth;
+
+mod batch;
+mod buffer;
+mod client;
+
+pub const READINGS_TABLE: &str = "readings";
+/// Tag holding the device name of every point
+pub const DEVICE_NAME_TAG: &str = "device_name";
+
+pub struct InfluxDbConnectionParameters<'a> {
+    pub server: &'a str,
+    pub api: InfluxDbApi<'a>,
+    /// Measurement of the events that do not set one
+    pub measurement: &'a str,
+    /// Retention policy of the events that do not set one, the database default when `None`. Only used by the v1 API
+    pub retention_policy: Option<&'a str>,
+    /// Event fields that are never written
+    pub exclude_fields: &'a [String],
+    pub batch: InfluxDbBatchParameters,
+    /// Retries the failed batches, before buffering them. A `RetrySink` would only retry the point that filled the batch
+    pub retry: Option<RetryParameters>,
+    /// Stores the points that could not be written on disk, to write them once InfluxDB is back
+    pub buffer: Option<InfluxDbBufferParameters<'a>>,
+}
+
+pub enum InfluxDbApi<'a> {
+    /// InfluxDB 1.x `/write` endpoint
+    V1 {
+        db: &'a str,
+        credentials: Option<InfluxDbCredentials<'a>>,
+    },
+    /// `/api/v2/write` endpoint, available on InfluxDB 2.x and 3.x
+    V2 { org: &'a str, bucket: &'a str, token: &'a str },
+}
+
+pub struct InfluxDbCredentials<'a> {
+    pub username: &'a str,
+    pub password: &'a str,
+}
+
+pub struct InfluxDbSink {
+    batcher: Arc<Batcher>,
+    measurement: String,
+    retention_policy: Option<String>,
+    exclude_fields: Vec<String>,
+}
+
+/// Retention policy names end up in buffer file names, so they are restricted to letters, digits, `_` and `-`.
+pub fn validate_retention_policy(name: &str) -> Result<()> {
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+        return Err(AppError::Influx(format!(
+            "Invalid retention policy [{}], only letters, digits, '_' and '-' are allowed",
+            name
+        ))
+        .into());
+    }
+    Ok(())
+}
+
+/// Builds the point for `event`, with every field except the ones in `exclude_fields`. The point goes to the event
+/// measurement, `READINGS_TABLE` when it has none. Returns `None` when there is no field left to write.
+pub fn event_query(event: Event, exclude_fields: &[String]) -> Option<WriteQuery> {
+    let mut fields = event.fields.iter().filter(|(name, _)| !exclude_fields.contains(name)).peekable();
+    fields.peek()?;
+
+    let mut query = Timestamp::from(event.time()).into_query(event.measurement.as_deref().unwrap_or(READINGS_TABLE));
+    for (name, value) in fields {
+        query = match value {
+            FieldValue::Boolean(v) => query.add_field(name.as_str(), *v),
+            FieldValue::Integer(v) => query.add_field(name.as_str(), *v),
+            FieldValue::Float(v) => query.add_field(name.as_str(), *v),
+            FieldValue::String(v) => query.add_field(name.as_str(), v.as_str()),
+        };
+    }
+    for (name, value) in event.tags.iter() {
+        query = query.add_tag(name.as_str(), value.as_str());
+    }
+    Some(query.add_tag(DEVICE_NAME_TAG, event.device_name))
+}
+
+impl InfluxDbSink {
+    pub async fn new(params: InfluxDbConnectionParameters<'_>) -> Result<Self> {
+        if let Some(retention_policy) = params.retention_policy {
+            validate_retention_policy(retention_policy)?;
+        }
+        let client = InfluxDbClient::new(params.server, &params.api)?;
+        let buffer = params.buffer.as_ref().map(DiskBuffer::open).transpose()?;
+        match client.ping().await {
+            Ok(health) => info!(
+                "Successfully connected to InfluxDB [build_type={}] [version={}]",
+                health.build_type, health.version
+            ),
+            // With a buffer, points are kept on disk until InfluxDB is back, so it does not have to be up to start
+            Err(e) if buffer.is_some() => warn!("InfluxDB unavailable, buffering points until it is back: {:#}", e),
+            Err(e) => return Err(e.context("Error checking connection to InfluxDB")),
+        }
+        Ok(Self {
+            batcher: Batcher::new(client, params.batch, params.retry.unwrap_or_default(), buffer),
+            measurement: params.measurement.to_string(),
+            retention_policy: params.retention_policy.map(str::to_string),
+            exclude_fields: params.exclude_fields.to_vec(),
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl EventSink for InfluxDbSink {
+    async fn sink(&self, mut event: Event) -> Result<()> {
+        event.measurement.get_or_insert_with(|| self.measurement.clone());
+        let retention_policy = event.retention_policy.take().or_else(|| self.retention_policy.clone());
+        let query = match event_query(event, &self.exclude_fields) {
+            Some(query) => query,
+            None => {
+                debug!("Event has no fields to store into InfluxDb");
+                return Ok(());
+            }
+        };
+
+        let line = query.build()?.get();
+        self.batcher.push(line, retention_policy).await
+    }
+
+    async fn flush(&self) -> Result<()> {
+        self.batcher.flush(FlushReason::Requested).await
+    }
+
+    async fn close(&self) -> Result<()> {
+        self.batcher.flush(FlushReason::Shutdown).await
+    }
+}
@@ -0,0 +1,40 @@
+use super::EventSink;
+use crate::{Event, Metrics};
+use anyhow::Result;
+use std::sync::Arc;
+use std::time::Instant;
+
+/// Sink that records the latency and the failures of the inner sink into `Metrics`.
+pub struct MeasuredSink {
+    name: String,
+    inner: Arc<dyn EventSink>,
+    metrics: Metrics,
+}
+
+impl MeasuredSink {
+    pub fn new(name: &str, inner: Arc<dyn EventSink>, metrics: Metrics) -> Self {
+        Self {
+            name: name.to_string(),
+            inner,
+            metrics,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl EventSink for MeasuredSink {
+    async fn sink(&self, event: Event) -> Result<()> {
+        let started = Instant::now();
+        let res = self.inner.sink(event).await;
+        self.metrics.sink_called(&self.name, started.elapsed(), res.is_err());
+        res
+    }
+
+    async fn flush(&self) -> Result<()> {
+        self.inner.flush().await
+    }
+
+    async fn close(&self) -> Result<()> {
+        self.inner.close().await
+    }
+}
@@ -1,15 +1,35 @@
 use crate::types::*;
 use anyhow::Result;
 
+pub use filter::*;
 pub use influx::*;
 pub use log::*;
-pub use tee::*;
+pub use measured::*;
+pub use queue::*;
+pub use registry::*;
+pub use retry::*;
+pub use set::*;
 
+mod filter;
 pub mod influx;
 mod log;
-mod tee;
+mod measured;
+mod queue;
+mod registry;
+mod retry;
+mod set;
 
 #[async_trait::async_trait]
 pub trait EventSink: Send + Sync {
     async fn sink(&self, event: Event) -> Result<()>;
+
+    /// Writes whatever the sink holds in memory, such as a pending batch.
+    async fn flush(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Called once before the application stops, after the last event. Flushes by default.
+    async fn close(&self) -> Result<()> {
+        self.flush().await
+    }
 }
@@ -0,0 +1,202 @@
+use super::EventSink;
+use crate::Event;
+use anyhow::Result;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::Notify;
+
+/// What a `QueuedSink` does with a new event when its queue is full.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OverflowPolicy {
+    /// Waits for room in the queue, slowing down the caller
+    #[default]
+    Block,
+    /// Drops the oldest queued event to make room for the new one
+    DropOldest,
+    /// Drops the new event
+    DropNewest,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct QueueParameters {
+    /// Maximum number of events waiting for the inner sink
+    pub capacity: usize,
+    pub overflow: OverflowPolicy,
+}
+
+impl Default for QueueParameters {
+    fn default() -> Self {
+        Self {
+            capacity: 1000,
+            overflow: OverflowPolicy::Block,
+        }
+    }
+}
+
+/// Snapshot of a `QueuedSink` queue, for monitoring.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize)]
+pub struct QueueStats {
+    pub name: String,
+    pub depth: usize,
+    pub capacity: usize,
+    /// Events dropped because the queue was full
+    pub dropped: u64,
+    /// Events the inner sink failed to store
+    pub failed: u64,
+}
+
+struct Shared {
+    events: Mutex<VecDeque<Event>>,
+    not_empty: Notify,
+    not_full: Notify,
+    /// Notified once every queued event has gone through the inner sink
+    drained: Notify,
+    /// Queued events plus the one being stored
+    pending: AtomicUsize,
+    closed: AtomicBool,
+    dropped: AtomicU64,
+    failed: AtomicU64,
+}
+
+/// Sink that queues events and stores them into the inner sink from its own task, so a slow sink does not hold
+/// back the caller. Events are considered stored once queued: failures of the inner sink are only logged and counted.
+pub struct QueuedSink {
+    name: String,
+    inner: Arc<dyn EventSink>,
+    params: QueueParameters,
+    shared: Arc<Shared>,
+}
+
+impl QueuedSink {
+    pub fn new(name: &str, inner: Arc<dyn EventSink>, params: QueueParameters) -> Self {
+        let shared = Arc::new(Shared {
+            events: Mutex::new(VecDeque::with_capacity(params.capacity)),
+            not_empty: Notify::new(),
+            not_full: Notify::new(),
+            drained: Notify::new(),
+            pending: AtomicUsize::new(0),
+            closed: AtomicBool::new(false),
+            dropped: AtomicU64::new(0),
+            failed: AtomicU64::new(0),
+        });
+        tokio::spawn(Self::work(name.to_string(), inner.clone(), shared.clone()));
+        Self {
+            name: name.to_string(),
+            inner,
+            params,
+            shared,
+        }
+    }
+
+    /// Stores the queued events one at a time. Once the sink is dropped, the remaining events are stored before stopping.
+    async fn work(name: String, inner: Arc<dyn EventSink>, shared: Arc<Shared>) {
+        loop {
+            let event = shared.events.lock().expect("Queue lock poisoned").pop_front();
+            let event = match event {
+                Some(event) => event,
+                None if shared.closed.load(Ordering::SeqCst) => return,
+                None => {
+                    shared.not_empty.notified().await;
+                    continue;
+                }
+            };
+            shared.not_full.notify_one();
+            if let Err(e) = inner.sink(event).await {
+                shared.failed.fetch_add(1, Ordering::Relaxed);
+                error!("Error sinking queued event into [{}]: {}", name, e);
+            }
+            if shared.pending.fetch_sub(1, Ordering::SeqCst) == 1 {
+                shared.drained.notify_waiters();
+            }
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Events waiting for the inner sink.
+    pub fn depth(&self) -> usize {
+        self.shared.events.lock().expect("Queue lock poisoned").len()
+    }
+
+    pub fn stats(&self) -> QueueStats {
+        QueueStats {
+            name: self.name.clone(),
+            depth: self.depth(),
+            capacity: self.params.capacity,
+            dropped: self.shared.dropped.load(Ordering::Relaxed),
+            failed: self.shared.failed.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl Drop for QueuedSink {
+    fn drop(&mut self) {
+        self.shared.closed.store(true, Ordering::SeqCst);
+        self.shared.not_empty.notify_one();
+    }
+}
+
+#[async_trait::async_trait]
+impl EventSink for QueuedSink {
+    async fn sink(&self, event: Event) -> Result<()> {
+        let mut event = Some(event);
+        loop {
+            {
+                let mut events = self.shared.events.lock().expect("Queue lock poisoned");
+                if events.len() < self.params.capacity {
+                    events.extend(event.take());
+                    self.shared.pending.fetch_add(1, Ordering::SeqCst);
+                } else {
+                    match self.params.overflow {
+                        OverflowPolicy::Block => {}
+                        OverflowPolicy::DropOldest => {
+                            events.pop_front();
+                            events.extend(event.take());
+                        }
+                        OverflowPolicy::DropNewest => {
+                            event.take();
+                        }
+                    }
+                    if event.is_none() {
+                        self.shared.dropped.fetch_add(1, Ordering::Relaxed);
+                        warn!(
+                            "Queue of [{}] is full, dropped an event [policy={:?}]",
+                            self.name, self.params.overflow
+                        );
+                    }
+                }
+            }
+            if event.is_none() {
+                self.shared.not_empty.notify_one();
+                return Ok(());
+            }
+            debug!("Queue of [{}] is full, waiting for room", self.name);
+            // `notify_one` keeps a permit when nobody is waiting, so a slot freed since the check is not missed
+            self.shared.not_full.notified().await;
+        }
+    }
+
+    /// Waits for the queued events to go through the inner sink, then flushes it.
+    async fn flush(&self) -> Result<()> {
+        loop {
+            let drained = self.shared.drained.notified();
+            tokio::pin!(drained);
+            // Registered before checking, as `notify_waiters` only wakes the current waiters
+            drained.as_mut().enable();
+            if self.shared.pending.load(Ordering::SeqCst) == 0 {
+                break;
+            }
+            drained.await;
+        }
+        self.inner.flush().await
+    }
+
+    async fn close(&self) -> Result<()> {
+        self.flush().await?;
+        self.inner.close().await
+    }
+}
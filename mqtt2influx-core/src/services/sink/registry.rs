@@ -0,0 +1,146 @@
+use super::{
+    EventFilter, EventSink, FilterSink, LogSink, MeasuredSink, QueueParameters, QueuedSink, RetryParameters, RetrySink, SinkSet,
+    SinkSetPolicy,
+};
+use crate::{AppError, Metrics};
+use anyhow::{Context, Result};
+use futures::future::BoxFuture;
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::sync::Arc;
+
+/// Sink to build, as described in the configuration.
+#[derive(Clone)]
+pub struct SinkSpec {
+    /// Name used in logs and errors, unique among the sinks
+    pub name: String,
+    /// Registered type of the sink, such as `influx` or `log`
+    pub sink_type: String,
+    /// Type specific options, parsed by the factory of the type
+    pub options: serde_json::Value,
+    /// Retries failed events when set, with a `RetrySink` unless the type retries by itself
+    pub retry: Option<RetryParameters>,
+    /// Stores events from a queue with its own task when set
+    pub queue: Option<QueueParameters>,
+    /// Only passes the accepted events to the sink when set
+    pub filter: Option<EventFilter>,
+}
+
+pub type SinkFactory = Box<dyn Fn(SinkSpec) -> BoxFuture<'static, Result<Arc<dyn EventSink>>> + Send + Sync>;
+
+/// Sinks built from their specs.
+pub struct SinkGraph {
+    pub sinks: SinkSet,
+    /// Queues of the sinks that have one, for monitoring
+    pub queues: Vec<Arc<QueuedSink>>,
+}
+
+/// Factories of the sink types that can be used in the configuration. `log` is always available.
+pub struct SinkRegistry {
+    factories: HashMap<String, SinkFactory>,
+    /// Types whose factory applies `SinkSpec::retry` itself
+    retrying: HashSet<String>,
+    metrics: Option<Metrics>,
+}
+
+impl Default for SinkRegistry {
+    fn default() -> Self {
+        let mut registry = Self {
+            factories: HashMap::new(),
+            retrying: HashSet::new(),
+            metrics: None,
+        };
+        registry.register("log", |_| async { Ok(Arc::new(LogSink) as Arc<dyn EventSink>) });
+        registry
+    }
+}
+
+impl SinkRegistry {
+    /// Registers the factory of `sink_type`, replacing the previous one if any.
+    pub fn register<F, Fut>(&mut self, sink_type: &str, factory: F)
+    where
+        F: Fn(SinkSpec) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<Arc<dyn EventSink>>> + Send + 'static,
+    {
+        self.retrying.remove(sink_type);
+        self.factories
+            .insert(sink_type.to_string(), Box::new(move |spec| Box::pin(factory(spec))));
+    }
+
+    /// Registers the factory of a type that retries by itself, such as one writing events in batches, where retrying
+    /// the event that failed would not retry the others. The factory gets the retry parameters in `SinkSpec::retry`
+    /// and the built sink is not wrapped into a `RetrySink`.
+    pub fn register_retrying<F, Fut>(&mut self, sink_type: &str, factory: F)
+    where
+        F: Fn(SinkSpec) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<Arc<dyn EventSink>>> + Send + 'static,
+    {
+        self.register(sink_type, factory);
+        self.retrying.insert(sink_type.to_string());
+    }
+
+    /// Records the latency and the failures of every built sink, before its retries, and the events filtered out of it.
+    pub fn with_metrics(mut self, metrics: Metrics) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Registered types, sorted.
+    pub fn types(&self) -> Vec<&str> {
+        let mut types = self.factories.keys().map(String::as_str).collect::<Vec<_>>();
+        types.sort_unstable();
+        types
+    }
+
+    /// Builds every sink, wrapped into its retries, queue and filter, and gathers them into a `SinkSet`.
+    /// Filtered out events never reach the queue.
+    pub async fn build(&self, specs: Vec<SinkSpec>, policy: SinkSetPolicy) -> Result<SinkGraph> {
+        if specs.is_empty() {
+            return Err(AppError::Config("At least one sink must be configured".to_string()).into());
+        }
+
+        let mut sinks = SinkSet::new(policy);
+        let mut queues = Vec::new();
+        let mut names = Vec::new();
+        for spec in specs {
+            if names.contains(&spec.name) {
+                return Err(AppError::Config(format!("Duplicated sink name [{}]", spec.name)).into());
+            }
+            let factory = self.factories.get(&spec.sink_type).ok_or_else(|| {
+                AppError::Config(format!(
+                    "Unknown type [{}] for sink [{}], must be one of {:?}",
+                    spec.sink_type,
+                    spec.name,
+                    self.types()
+                ))
+            })?;
+
+            let name = spec.name.clone();
+            let retry = spec.retry.clone().filter(|_| !self.retrying.contains(&spec.sink_type));
+            let (queue, filter) = (spec.queue, spec.filter.clone());
+            let mut sink = factory(spec).await.with_context(|| format!("Error creating sink [{}]", name))?;
+            if let Some(metrics) = &self.metrics {
+                sink = Arc::new(MeasuredSink::new(&name, sink, metrics.clone()));
+            }
+            if let Some(retry) = retry {
+                sink = Arc::new(RetrySink::new(&name, sink, retry));
+            }
+            if let Some(queue) = queue {
+                let queued = Arc::new(QueuedSink::new(&name, sink, queue));
+                queues.push(queued.clone());
+                sink = queued;
+            }
+            if let Some(filter) = filter {
+                let filtered = FilterSink::new(&name, sink, filter);
+                sink = match &self.metrics {
+                    Some(metrics) => Arc::new(filtered.with_metrics(metrics.clone())),
+                    None => Arc::new(filtered),
+                };
+            }
+            info!("Sink created [name={}]", name);
+            sinks = sinks.with_sink(&name, sink);
+            names.push(name);
+        }
+        Ok(SinkGraph { sinks, queues })
+    }
+}
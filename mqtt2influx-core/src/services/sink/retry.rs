@@ -0,0 +1,88 @@
+use super::EventSink;
+use crate::utils::Backoff;
+use crate::{Event, SinkError};
+use anyhow::Result;
+use std::sync::Arc;
+
+/// Decides whether a failed sink call is worth retrying.
+pub type RetryClassifier = Arc<dyn Fn(&anyhow::Error) -> bool + Send + Sync>;
+
+/// Retries errors the sink reports as retryable, see `SinkError::is_retryable`.
+pub fn default_retry_classifier() -> RetryClassifier {
+    Arc::new(|e| SinkError::find(e).map(SinkError::is_retryable).unwrap_or(false))
+}
+
+#[derive(Clone)]
+pub struct RetryParameters {
+    /// Total calls to the inner sink, including the first one. 1 disables retries
+    pub max_attempts: u32,
+    pub backoff: Backoff,
+    pub classifier: RetryClassifier,
+}
+
+impl Default for RetryParameters {
+    fn default() -> Self {
+        Self {
+            max_attempts: 1,
+            backoff: Backoff::default(),
+            classifier: default_retry_classifier(),
+        }
+    }
+}
+
+/// Sink that calls the inner sink again, with backoff, while it fails with retryable errors.
+pub struct RetrySink<S>
+where
+    S: EventSink + ?Sized,
+{
+    name: String,
+    inner: Arc<S>,
+    params: RetryParameters,
+}
+
+impl<S> RetrySink<S>
+where
+    S: EventSink + ?Sized,
+{
+    pub fn new(name: &str, inner: Arc<S>, params: RetryParameters) -> Self {
+        Self {
+            name: name.to_string(),
+            inner,
+            params,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl<S> EventSink for RetrySink<S>
+where
+    S: EventSink + ?Sized,
+{
+    async fn sink(&self, event: Event) -> Result<()> {
+        let mut attempt = 1;
+        loop {
+            let e = match self.inner.sink(event.clone()).await {
+                Ok(_) => return Ok(()),
+                Err(e) => e,
+            };
+            if attempt >= self.params.max_attempts || !(self.params.classifier)(&e) {
+                return Err(e);
+            }
+            let delay = self.params.backoff.delay(attempt);
+            warn!(
+                "Error sinking event into [{}]: {}. Retrying in {:?} [attempt={}/{}]",
+                self.name, e, delay, attempt, self.params.max_attempts
+            );
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+
+    async fn flush(&self) -> Result<()> {
+        self.inner.flush().await
+    }
+
+    async fn close(&self) -> Result<()> {
+        self.inner.close().await
+    }
+}
@@ -0,0 +1,138 @@
+use super::EventSink;
+use crate::Event;
+use anyhow::Result;
+use std::fmt;
+use std::sync::Arc;
+
+/// When a `SinkSet` considers an event stored.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SinkSetPolicy {
+    /// Every member must store the event
+    #[default]
+    All,
+    /// Failures are logged and ignored
+    BestEffort,
+    /// At least this many members must store the event
+    Quorum(usize),
+}
+
+/// Member of a `SinkSet` that failed to store an event.
+#[derive(Debug)]
+pub struct SinkFailure {
+    pub sink: String,
+    pub error: anyhow::Error,
+}
+
+/// Failure of a `SinkSet`, with every member that failed. Its source is the error of the first failed member,
+/// so `SinkError::find` still classifies it.
+#[derive(Debug)]
+pub struct SinkSetError {
+    pub failures: Vec<SinkFailure>,
+    /// Members that stored the event
+    pub succeeded: usize,
+    /// Members required to store the event
+    pub required: usize,
+}
+
+impl SinkSetError {
+    /// Names of the failed members.
+    pub fn failed_sinks(&self) -> Vec<&str> {
+        self.failures.iter().map(|f| f.sink.as_str()).collect()
+    }
+}
+
+impl fmt::Display for SinkSetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} sinks succeeded, {} required", self.succeeded, self.required)?;
+        for failure in self.failures.iter() {
+            write!(f, ". [{}]: {}", failure.sink, failure.error)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for SinkSetError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.failures
+            .first()
+            .map(|f| f.error.as_ref() as &(dyn std::error::Error + 'static))
+    }
+}
+
+/// Sink that sends every event to all its members concurrently.
+pub struct SinkSet {
+    members: Vec<(String, Arc<dyn EventSink>)>,
+    policy: SinkSetPolicy,
+}
+
+impl SinkSet {
+    pub fn new(policy: SinkSetPolicy) -> Self {
+        Self {
+            members: Vec::new(),
+            policy,
+        }
+    }
+
+    pub fn with_sink(mut self, name: &str, sink: Arc<dyn EventSink>) -> Self {
+        self.members.push((name.to_string(), sink));
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.members.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.members.is_empty()
+    }
+
+    fn required(&self) -> usize {
+        match self.policy {
+            SinkSetPolicy::All => self.members.len(),
+            SinkSetPolicy::BestEffort => 0,
+            SinkSetPolicy::Quorum(quorum) => quorum,
+        }
+    }
+
+    /// Checks the results of the members, in the order they were added, against the `required` successes.
+    fn outcome(&self, results: Vec<Result<()>>, required: usize, action: &str) -> Result<()> {
+        let mut failures = Vec::new();
+        for ((name, _), res) in self.members.iter().zip(results) {
+            if let Err(error) = res {
+                warn!("Error {} [{}]: {}", action, name, error);
+                failures.push(SinkFailure { sink: name.clone(), error });
+            }
+        }
+
+        let succeeded = self.members.len() - failures.len();
+        if succeeded >= required {
+            return Ok(());
+        }
+        Err(SinkSetError {
+            failures,
+            succeeded,
+            required,
+        }
+        .into())
+    }
+}
+
+#[async_trait::async_trait]
+impl EventSink for SinkSet {
+    async fn sink(&self, event: Event) -> Result<()> {
+        let results = futures::future::join_all(self.members.iter().map(|(_, sink)| sink.sink(event.clone()))).await;
+        self.outcome(results, self.required(), "sinking event into")
+    }
+
+    /// Every member is flushed, whatever the policy.
+    async fn flush(&self) -> Result<()> {
+        let results = futures::future::join_all(self.members.iter().map(|(_, sink)| sink.flush())).await;
+        self.outcome(results, self.members.len(), "flushing")
+    }
+
+    async fn close(&self) -> Result<()> {
+        let results = futures::future::join_all(self.members.iter().map(|(_, sink)| sink.close())).await;
+        self.outcome(results, self.members.len(), "closing")
+    }
+}
@@ -0,0 +1,33 @@
+use std::sync::Arc;
+use tokio::sync::watch;
+
+/// Signal telling the pipeline to stop. Clones share the same signal.
+#[derive(Clone, Debug)]
+pub struct Shutdown {
+    tx: Arc<watch::Sender<bool>>,
+    rx: watch::Receiver<bool>,
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        let (tx, rx) = watch::channel(false);
+        Self { tx: Arc::new(tx), rx }
+    }
+}
+
+impl Shutdown {
+    pub fn trigger(&self) {
+        self.tx.send_replace(true);
+    }
+
+    pub fn is_triggered(&self) -> bool {
+        *self.rx.borrow()
+    }
+
+    /// Resolves once the shutdown has been triggered.
+    pub async fn triggered(&self) {
+        let mut rx = self.rx.clone();
+        // The sender lives as long as `self`, so waiting cannot fail
+        let _ = rx.wait_for(|triggered| *triggered).await;
+    }
+}
@@ -0,0 +1,149 @@
+use crate::AppError;
+use anyhow::Result;
+
+const SINGLE_LEVEL_WILDCARD: &str = "+";
+const MULTI_LEVEL_WILDCARD: &str = "#";
+
+/// Checks that `filter` is a valid MQTT topic filter and returns the number of wildcards it contains.
+pub fn validate_filter(filter: &str) -> Result<usize> {
+    if filter.is_empty() {
+        return Err(AppError::Topic("Topic filter cannot be empty".to_string()).into());
+    }
+
+    let levels: Vec<&str> = filter.split('/').collect();
+    let mut wildcards = 0;
+    for (idx, level) in levels.iter().enumerate() {
+        if *level == MULTI_LEVEL_WILDCARD {
+            if idx != levels.len() - 1 {
+                return Err(AppError::Topic(format!("'#' must be the last level of the filter [{}]", filter)).into());
+            }
+            wildcards += 1;
+        } else if *level == SINGLE_LEVEL_WILDCARD {
+            wildcards += 1;
+        } else if level.contains('+') || level.contains('#') {
+            return Err(AppError::Topic(format!("Wildcards must occupy a whole level in the filter [{}]", filter)).into());
+        }
+    }
+    Ok(wildcards)
+}
+
+/// Matches `topic` against `filter` following the MQTT spec.
+///
+/// Returns the segments captured by each wildcard, in order. A `#` captures the remaining levels joined by `/`.
+pub fn match_topic<'a>(filter: &str, topic: &'a str) -> Option<Vec<&'a str>> {
+    // Wildcards at the first level never match topics starting with '$'
+    if topic.starts_with('$') && (filter.starts_with(SINGLE_LEVEL_WILDCARD) || filter.starts_with(MULTI_LEVEL_WILDCARD)) {
+        return None;
+    }
+
+    let mut captures = Vec::new();
+    let mut topic_levels = topic.split('/');
+    let mut consumed = 0;
+    for filter_level in filter.split('/') {
+        if filter_level == MULTI_LEVEL_WILDCARD {
+            // "a/#" also matches "a", capturing nothing
+            let rest = if consumed >= topic.len() { "" } else { &topic[consumed..] };
+            captures.push(rest);
+            return Some(captures);
+        }
+
+        let topic_level = topic_levels.next()?;
+        consumed += topic_level.len() + 1;
+        if filter_level == SINGLE_LEVEL_WILDCARD {
+            captures.push(topic_level);
+        } else if filter_level != topic_level {
+            return None;
+        }
+    }
+
+    match topic_levels.next() {
+        Some(_) => None,
+        None => Some(captures),
+    }
+}
+
+enum TemplatePart<'a> {
+    Literal(&'a str),
+    Capture(usize),
+}
+
+fn parse_template(template: &str) -> Result<Vec<TemplatePart<'_>>> {
+    let mut parts = Vec::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        parts.push(TemplatePart::Literal(&rest[..start]));
+        let after = &rest[start + 1..];
+        let end = after
+            .find('}')
+            .ok_or_else(|| AppError::Topic(format!("Unclosed placeholder in template [{}]", template)))?;
+        let placeholder = &after[..end];
+        match placeholder.parse::<usize>() {
+            Ok(index) if index > 0 => parts.push(TemplatePart::Capture(index)),
+            _ => {
+                return Err(AppError::Topic(format!("Invalid placeholder [{{{}}}] in template [{}]", placeholder, template)).into());
+            }
+        }
+        rest = &after[end + 1..];
+    }
+    parts.push(TemplatePart::Literal(rest));
+    Ok(parts)
+}
+
+/// Returns the highest `{n}` placeholder used in `template`, or 0 if there is none.
+pub fn max_placeholder(template: &str) -> Result<usize> {
+    let max = parse_template(template)?
+        .into_iter()
+        .map(|part| match part {
+            TemplatePart::Capture(index) => index,
+            TemplatePart::Literal(_) => 0,
+        })
+        .max()
+        .unwrap_or(0);
+    Ok(max)
+}
+
+/// Replaces every `{n}` placeholder in `template` with the n-th capture (1-based).
+pub fn render_template(template: &str, captures: &[&str]) -> Result<String> {
+    let mut rendered = String::with_capacity(template.len());
+    for part in parse_template(template)? {
+        match part {
+            TemplatePart::Literal(literal) => rendered.push_str(literal),
+            TemplatePart::Capture(index) => match captures.get(index - 1) {
+                Some(capture) => rendered.push_str(capture),
+                None => return Err(AppError::Topic(format!("Placeholder [{{{}}}] has no matching wildcard", index)).into()),
+            },
+        }
+    }
+    Ok(rendered)
+}
+
+/// Matches `text` against a glob `pattern`, where `*` matches any sequence of characters, `/` included,
+/// and `?` matches a single character.
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let text = text.chars().collect::<Vec<_>>();
+    let (mut p, mut t) = (0, 0);
+    // Position of the last `*` and of the text it is matched against, to backtrack when the rest does not match
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some(c) if *c == '?' || *c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                Some((star_p, star_t)) => {
+                    p = star_p + 1;
+                    t = star_t + 1;
+                    star = Some((star_p, star_t + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
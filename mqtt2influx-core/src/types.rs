@@ -1,37 +1,355 @@
-#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
-pub struct RawMqttEvent {
-    pub battery: u8,
-    pub humidity: f32,
-    pub temperature: f32,
-    pub voltage: u16,
-    pub linkquality: Option<u8>,
+use crate::sink::influx::{validate_retention_policy, DEVICE_NAME_TAG};
+use crate::{topic, AppError};
+use chrono::{DateTime, TimeZone, Utc};
+use rumqttc::QoS;
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(untagged)]
+pub enum FieldValue {
+    Boolean(bool),
+    Integer(i64),
+    Float(f64),
+    String(String),
+}
+
+impl FieldValue {
+    /// Numeric value of the field, booleans and strings are not converted.
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            FieldValue::Integer(v) => Some(*v as f64),
+            FieldValue::Float(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    /// Converts a JSON value, inferring the type when `field_type` is `None`.
+    /// Nulls, objects, arrays and values that cannot be converted return `None`.
+    pub fn from_json(value: &Value, field_type: Option<FieldType>) -> Option<Self> {
+        match (field_type, value) {
+            (None, Value::Bool(b)) => Some(FieldValue::Boolean(*b)),
+            (None, Value::Number(n)) => n.as_i64().map(FieldValue::Integer).or_else(|| n.as_f64().map(FieldValue::Float)),
+            (None, Value::String(s)) => Some(FieldValue::String(s.clone())),
+            (Some(FieldType::Float), Value::Number(n)) => n.as_f64().map(FieldValue::Float),
+            (Some(FieldType::Integer), Value::Number(n)) => n
+                .as_i64()
+                .or_else(|| n.as_f64().filter(|f| f.fract() == 0.0).map(|f| f as i64))
+                .map(FieldValue::Integer),
+            (Some(FieldType::Boolean), Value::Bool(b)) => Some(FieldValue::Boolean(*b)),
+            (Some(FieldType::Boolean), Value::String(s)) => match s.to_lowercase().as_str() {
+                "on" | "true" => Some(FieldValue::Boolean(true)),
+                "off" | "false" => Some(FieldValue::Boolean(false)),
+                _ => None,
+            },
+            (Some(FieldType::String), Value::String(s)) => Some(FieldValue::String(s.clone())),
+            (Some(FieldType::String), Value::Number(n)) => Some(FieldValue::String(n.to_string())),
+            (Some(FieldType::String), Value::Bool(b)) => Some(FieldValue::String(b.to_string())),
+            _ => None,
+        }
+    }
 }
 
-#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+impl fmt::Display for FieldValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FieldValue::Boolean(v) => write!(f, "{}", v),
+            FieldValue::Integer(v) => write!(f, "{}", v),
+            FieldValue::Float(v) => write!(f, "{}", v),
+            FieldValue::String(v) => write!(f, "{}", v),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FieldType {
+    Float,
+    Integer,
+    Boolean,
+    String,
+}
+
+#[derive(Clone, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
 pub struct Event {
     pub device_name: String,
-    pub battery: u8,
-    pub humidity: f32,
-    pub temperature: f32,
-    pub voltage: u16,
-    pub linkquality: Option<u8>,
+    /// MQTT topic the event was received on
+    #[serde(default)]
+    pub topic: Option<String>,
+    #[serde(default)]
+    pub tags: BTreeMap<String, String>,
+    #[serde(default)]
+    pub fields: BTreeMap<String, FieldValue>,
+    /// When the message was received from the broker
+    #[serde(default = "Utc::now")]
+    pub received_at: DateTime<Utc>,
+    /// Time of the reading as reported in the payload, see `Subscription::time_field`
+    #[serde(default)]
+    pub time: Option<DateTime<Utc>>,
+    /// Measurement the event is stored into, the sink default when `None`
+    #[serde(default)]
+    pub measurement: Option<String>,
+    /// InfluxDB retention policy the event is stored into, the sink default when `None`
+    #[serde(default)]
+    pub retention_policy: Option<String>,
 }
 
 impl Event {
-    pub fn from_mqtt(source: RawMqttEvent, device_name: &str) -> Self {
+    pub fn new(device_name: &str) -> Self {
         Self {
             device_name: device_name.to_string(),
-            battery: source.battery,
-            humidity: source.humidity,
-            temperature: source.temperature,
-            voltage: source.voltage,
-            linkquality: source.linkquality,
+            topic: None,
+            tags: BTreeMap::new(),
+            fields: BTreeMap::new(),
+            received_at: Utc::now(),
+            time: None,
+            measurement: None,
+            retention_policy: None,
         }
     }
+
+    pub fn with_topic(mut self, topic: &str) -> Self {
+        self.topic = Some(topic.to_string());
+        self
+    }
+
+    pub fn with_received_at(mut self, received_at: DateTime<Utc>) -> Self {
+        self.received_at = received_at;
+        self
+    }
+
+    pub fn with_time(mut self, time: DateTime<Utc>) -> Self {
+        self.time = Some(time);
+        self
+    }
+
+    /// Time of the reading: the one in the payload if there is one, the reception time otherwise.
+    pub fn time(&self) -> DateTime<Utc> {
+        self.time.unwrap_or(self.received_at)
+    }
+
+    pub fn with_measurement(mut self, measurement: &str) -> Self {
+        self.measurement = Some(measurement.to_string());
+        self
+    }
+
+    pub fn with_retention_policy(mut self, retention_policy: &str) -> Self {
+        self.retention_policy = Some(retention_policy.to_string());
+        self
+    }
+
+    pub fn with_field(mut self, name: &str, value: FieldValue) -> Self {
+        self.fields.insert(name.to_string(), value);
+        self
+    }
+
+    pub fn with_tag(mut self, name: &str, value: &str) -> Self {
+        self.tags.insert(name.to_string(), value.to_string());
+        self
+    }
+
+    pub fn field(&self, name: &str) -> Option<&FieldValue> {
+        self.fields.get(name)
+    }
+
+    /// Builds an event from a JSON object payload, following the field mapping of `subscription`.
+    pub fn from_mqtt(payload: &Map<String, Value>, device_name: &str, subscription: &Subscription, received_at: DateTime<Utc>) -> Self {
+        let mut event = Event::new(device_name).with_received_at(received_at);
+        event.measurement = subscription.measurement.clone();
+        event.retention_policy = subscription.retention_policy.clone();
+        event.tags = subscription.tags.clone();
+        for (key, value) in payload.iter() {
+            if subscription.ignore.contains(key) {
+                continue;
+            }
+            if subscription.time_field.as_ref() == Some(key) {
+                event.time = subscription.time_format.parse(value);
+                if event.time.is_none() {
+                    warn!(
+                        "Could not parse [{}] as {:?} time, using the reception time [device={}] [value={}]",
+                        key, subscription.time_format, device_name, value
+                    );
+                }
+                continue;
+            }
+            if subscription.tag_keys.contains(key) {
+                match value {
+                    Value::String(s) => event.tags.insert(key.clone(), s.clone()),
+                    Value::Null | Value::Object(_) | Value::Array(_) => None,
+                    other => event.tags.insert(key.clone(), other.to_string()),
+                };
+                continue;
+            }
+            let field_type = if subscription.fields.is_empty() {
+                None
+            } else {
+                match subscription.fields.get(key) {
+                    Some(field_type) => Some(*field_type),
+                    None => continue,
+                }
+            };
+            match FieldValue::from_json(value, field_type) {
+                Some(field) => {
+                    event.fields.insert(key.clone(), field);
+                }
+                None if field_type.is_some() && !value.is_null() => {
+                    warn!(
+                        "Could not convert [{}] to {:?} [device={}] [value={}]",
+                        key, field_type, device_name, value
+                    );
+                }
+                None => trace!("Skipping [{}] [device={}] [value={}]", key, device_name, value),
+            }
+        }
+        event
+    }
 }
 
-#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+#[derive(Clone, Debug, Default, serde::Deserialize, serde::Serialize)]
 pub struct Subscription {
+    /// MQTT topic filter, may contain `+` and `#` wildcards
     pub topic: String,
+    /// Device name template, `{n}` is replaced by the segment captured by the n-th wildcard
     pub device_name: String,
+    /// MQTT QoS level (0, 1 or 2) requested for the subscription
+    #[serde(default)]
+    pub qos: u8,
+    /// Payload keys stored as fields, with their type. When empty, every key that is not a tag or ignored becomes a field
+    /// with its type inferred from the JSON value
+    #[serde(default)]
+    pub fields: HashMap<String, FieldType>,
+    /// Payload keys stored as tags
+    #[serde(default)]
+    pub tag_keys: Vec<String>,
+    /// Payload keys that are dropped
+    #[serde(default)]
+    pub ignore: Vec<String>,
+    /// Payload key holding the time of the reading, used instead of the time the message was received
+    pub time_field: Option<String>,
+    /// Format of `time_field`
+    #[serde(default)]
+    pub time_format: TimeFormat,
+    /// Measurement the readings are stored into, `influx.measurement` by default
+    pub measurement: Option<String>,
+    /// Static tags added to every reading, such as `room` or `site`
+    #[serde(default)]
+    pub tags: BTreeMap<String, String>,
+    /// InfluxDB v1 retention policy the readings are stored into, `influx.retention_policy` by default
+    pub retention_policy: Option<String>,
+    /// Milliseconds without readings after which a device is stale, and offline after twice as long.
+    /// Devices are not monitored when unset
+    pub max_silence_ms: Option<u64>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TimeFormat {
+    /// RFC 3339 / ISO-8601 string, such as Zigbee2MQTT's `last_seen`
+    #[default]
+    Iso8601,
+    /// Seconds since the epoch, may have decimals
+    EpochS,
+    /// Milliseconds since the epoch
+    EpochMs,
+}
+
+impl TimeFormat {
+    pub fn parse(&self, value: &Value) -> Option<DateTime<Utc>> {
+        match (self, value) {
+            (TimeFormat::Iso8601, Value::String(s)) => DateTime::parse_from_rfc3339(s).ok().map(|t| t.with_timezone(&Utc)),
+            (TimeFormat::EpochS, Value::Number(n)) => {
+                let millis = (n.as_f64()? * 1000.0).round() as i64;
+                Utc.timestamp_millis_opt(millis).single()
+            }
+            (TimeFormat::EpochMs, Value::Number(n)) => Utc.timestamp_millis_opt(n.as_i64()?).single(),
+            _ => None,
+        }
+    }
+}
+
+/// Reports an error of the topic or retention policy checks, shared with the runtime, as a configuration error.
+fn as_config_error(e: anyhow::Error) -> anyhow::Error {
+    match e.downcast::<AppError>() {
+        Ok(AppError::Topic(message)) | Ok(AppError::Influx(message)) => AppError::Config(message).into(),
+        Ok(e) => e.into(),
+        Err(e) => e,
+    }
+}
+
+impl Subscription {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.qos > 2 {
+            return Err(AppError::Config(format!("Invalid qos [{}] for topic [{}], must be 0, 1 or 2", self.qos, self.topic)).into());
+        }
+        let wildcards = topic::validate_filter(&self.topic).map_err(as_config_error)?;
+        let placeholders = topic::max_placeholder(&self.device_name).map_err(as_config_error)?;
+        if placeholders > wildcards {
+            return Err(AppError::Config(format!(
+                "Device name [{}] uses {{{}}} but topic [{}] only has {} wildcards",
+                self.device_name, placeholders, self.topic, wildcards
+            ))
+            .into());
+        }
+        for key in self.tag_keys.iter() {
+            if self.fields.contains_key(key) || self.ignore.contains(key) {
+                return Err(AppError::Config(format!("Key [{}] is mapped as a tag and as a field or ignored", key)).into());
+            }
+        }
+        if let Some(key) = &self.time_field {
+            if self.fields.contains_key(key) || self.tag_keys.contains(key) || self.ignore.contains(key) {
+                return Err(AppError::Config(format!("Key [{}] is the time field and cannot be mapped", key)).into());
+            }
+        }
+        // Every point is tagged with the device name, a second `device_name` tag would make InfluxDB reject it
+        if self.tags.contains_key(DEVICE_NAME_TAG) || self.tag_keys.iter().any(|key| key == DEVICE_NAME_TAG) {
+            return Err(AppError::Config(format!(
+                "Tag [{}] of topic [{}] is reserved for the device name",
+                DEVICE_NAME_TAG, self.topic
+            ))
+            .into());
+        }
+        for key in self.tags.keys() {
+            if self.tag_keys.contains(key) {
+                return Err(AppError::Config(format!("Tag [{}] is both static and read from the payload", key)).into());
+            }
+        }
+        if let Some(measurement) = &self.measurement {
+            if measurement.is_empty() {
+                return Err(AppError::Config(format!("Empty measurement for topic [{}]", self.topic)).into());
+            }
+        }
+        if let Some(retention_policy) = &self.retention_policy {
+            validate_retention_policy(retention_policy).map_err(as_config_error)?;
+        }
+        if self.max_silence_ms == Some(0) {
+            return Err(AppError::Config(format!("max_silence_ms of topic [{}] must be at least 1", self.topic)).into());
+        }
+        for key in self.ignore.iter() {
+            if self.fields.contains_key(key) {
+                return Err(AppError::Config(format!("Key [{}] is mapped as a field and ignored", key)).into());
+            }
+        }
+        Ok(())
+    }
+
+    pub fn qos(&self) -> QoS {
+        match self.qos {
+            0 => QoS::AtMostOnce,
+            1 => QoS::AtLeastOnce,
+            _ => QoS::ExactlyOnce,
+        }
+    }
+
+    /// Returns the device name for `topic` if it matches this subscription.
+    pub fn device_name_for(&self, topic: &str) -> Option<String> {
+        let captures = topic::match_topic(&self.topic, topic)?;
+        match topic::render_template(&self.device_name, &captures) {
+            Ok(device_name) => Some(device_name),
+            Err(e) => {
+                warn!("Could not render device name for topic [{}]: {}", topic, e);
+                None
+            }
+        }
+    }
 }
@@ -1,4 +1,5 @@
 use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use std::time::Duration;
 
 pub fn generate_random_token(size: usize) -> String {
     thread_rng().sample_iter(&Alphanumeric).take(size).collect()
@@ -7,3 +8,36 @@ pub fn generate_random_token(size: usize) -> String {
 pub fn generate_random_number(min: usize, max: usize) -> usize {
     thread_rng().gen_range(min, max)
 }
+
+/// Exponential backoff with jitter.
+#[derive(Clone, Debug)]
+pub struct Backoff {
+    pub initial_delay: Duration,
+    pub max_delay: Duration,
+    pub multiplier: f64,
+    /// Fraction (0 to 1) of each delay that is randomized away
+    pub jitter: f64,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self {
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(60),
+            multiplier: 2.0,
+            jitter: 0.2,
+        }
+    }
+}
+
+impl Backoff {
+    /// Returns the delay to wait before the given attempt, starting at 1.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(i32::MAX as u32) as i32;
+        let base = self.initial_delay.as_secs_f64() * self.multiplier.powi(exponent);
+        let capped = base.min(self.max_delay.as_secs_f64());
+        let jitter = self.jitter.clamp(0.0, 1.0);
+        let randomized = capped * (1.0 - jitter * thread_rng().gen::<f64>());
+        Duration::from_secs_f64(randomized)
+    }
+}
@@ -0,0 +1,111 @@
+use crate::test_tools::*;
+use mqtt2influx_core::chrono::{Duration, Utc};
+use mqtt2influx_core::{DeviceMonitor, DeviceStatus, DeviceStatuses, Event, EventSink, FieldValue, Subscription, STATUS_MEASUREMENT};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+fn subscriptions() -> Vec<Subscription> {
+    vec![
+        Subscription {
+            topic: "sensors/+".to_string(),
+            device_name: "{1}".to_string(),
+            tags: BTreeMap::from([("site".to_string(), "home".to_string())]),
+            max_silence_ms: Some(60_000),
+            ..Default::default()
+        },
+        Subscription {
+            topic: "plugs/+".to_string(),
+            device_name: "{1}".to_string(),
+            ..Default::default()
+        },
+    ]
+}
+
+fn monitor() -> (Arc<MockEventSink>, DeviceStatuses, DeviceMonitor) {
+    let inner = Arc::new(MockEventSink::default());
+    let statuses = DeviceStatuses::default();
+    let monitor = DeviceMonitor::new(subscriptions(), inner.clone(), statuses.clone());
+    (inner, statuses, monitor)
+}
+
+#[test]
+fn status_depends_on_the_silence() {
+    let max_silence = Duration::seconds(60);
+    assert_eq!(DeviceStatus::after(Duration::seconds(60), max_silence), DeviceStatus::Online);
+    assert_eq!(DeviceStatus::after(Duration::seconds(61), max_silence), DeviceStatus::Stale);
+    assert_eq!(DeviceStatus::after(Duration::seconds(121), max_silence), DeviceStatus::Offline);
+}
+
+#[tokio::test]
+async fn silent_devices_go_stale_then_offline() {
+    let (inner, statuses, monitor) = monitor();
+    let now = Utc::now();
+    let reading = random_event().with_topic("sensors/kitchen").with_received_at(now);
+    let reading = Event {
+        device_name: "kitchen".to_string(),
+        ..reading
+    };
+    monitor.sink(reading.clone()).await.expect("Should be able to sink");
+    assert_eq!(inner.received().await, vec![reading.clone()], "Reading should reach the inner sink");
+    assert_eq!(statuses.get("kitchen"), Some(DeviceStatus::Online), "Device should be online");
+
+    assert!(
+        monitor.sweep_at(now + Duration::seconds(30)).is_empty(),
+        "Nothing should be emitted"
+    );
+    assert_eq!(statuses.get("kitchen"), Some(DeviceStatus::Online), "Device should still be online");
+
+    assert!(
+        monitor.sweep_at(now + Duration::seconds(90)).is_empty(),
+        "Nothing should be emitted when stale"
+    );
+    assert_eq!(statuses.get("kitchen"), Some(DeviceStatus::Stale), "Device should be stale");
+
+    let offline = monitor.sweep_at(now + Duration::seconds(150));
+    assert_eq!(offline.len(), 1, "An offline event should be emitted");
+    assert_eq!(offline[0].device_name, "kitchen", "Device name should match");
+    assert_eq!(
+        offline[0].measurement.as_deref(),
+        Some(STATUS_MEASUREMENT),
+        "Measurement should match"
+    );
+    assert_eq!(
+        offline[0].field("status"),
+        Some(&FieldValue::String("offline".to_string())),
+        "Status should match"
+    );
+    assert_eq!(
+        offline[0].tags.get("site").map(String::as_str),
+        Some("home"),
+        "Static tags should be kept"
+    );
+    assert_eq!(statuses.get("kitchen"), Some(DeviceStatus::Offline), "Device should be offline");
+    assert!(
+        monitor.sweep_at(now + Duration::seconds(200)).is_empty(),
+        "Offline event should only be emitted once"
+    );
+
+    monitor
+        .sink(Event {
+            received_at: now + Duration::seconds(210),
+            ..reading
+        })
+        .await
+        .expect("Should be able to sink");
+    assert_eq!(statuses.get("kitchen"), Some(DeviceStatus::Online), "Device should be back online");
+}
+
+#[tokio::test]
+async fn devices_without_max_silence_are_not_monitored() {
+    let (inner, statuses, monitor) = monitor();
+    let now = Utc::now();
+    let reading = Event::new("plug")
+        .with_topic("plugs/plug")
+        .with_received_at(now)
+        .with_field("power", FieldValue::Float(3.5));
+    monitor.sink(reading).await.expect("Should be able to sink");
+
+    assert!(monitor.sweep_at(now + Duration::days(1)).is_empty(), "Nothing should be emitted");
+    assert_eq!(statuses.get("plug"), None, "Device should have no status");
+    assert_eq!(inner.received().await.len(), 1, "Reading should reach the inner sink");
+}
@@ -0,0 +1,246 @@
+use mqtt2influx_core::chrono::{DateTime, TimeZone, Utc};
+use mqtt2influx_core::{AppError, Event, FieldType, FieldValue, Subscription, TimeFormat};
+use serde_json::{json, Map, Value};
+
+fn received_at() -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(2021, 3, 1, 10, 0, 0).unwrap()
+}
+
+fn expected_event(device_name: &str) -> Event {
+    Event::new(device_name).with_received_at(received_at())
+}
+
+fn payload(value: Value) -> Map<String, Value> {
+    match value {
+        Value::Object(map) => map,
+        _ => panic!("Payload should be an object"),
+    }
+}
+
+fn subscription() -> Subscription {
+    Subscription {
+        topic: "zigbee2mqtt/+".to_string(),
+        device_name: "{1}".to_string(),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn fields_are_inferred_without_mapping() {
+    let payload = payload(json!({
+        "contact": true,
+        "battery": 90,
+        "temperature": 21.5,
+        "state": "ON",
+        "linkquality": null,
+        "update": {"state": "idle"},
+    }));
+    let event = Event::from_mqtt(&payload, "door", &subscription(), received_at());
+
+    let expected = expected_event("door")
+        .with_field("contact", FieldValue::Boolean(true))
+        .with_field("battery", FieldValue::Integer(90))
+        .with_field("temperature", FieldValue::Float(21.5))
+        .with_field("state", FieldValue::String("ON".to_string()));
+    assert_eq!(event, expected, "Nulls and nested values should be skipped");
+}
+
+#[test]
+fn mapping_selects_fields_tags_and_types() {
+    let subscription = Subscription {
+        fields: vec![
+            ("temperature".to_string(), FieldType::Float),
+            ("state".to_string(), FieldType::Boolean),
+            ("co2".to_string(), FieldType::Integer),
+        ]
+        .into_iter()
+        .collect(),
+        tag_keys: vec!["model".to_string()],
+        ..subscription()
+    };
+    let payload = payload(json!({
+        "temperature": 21,
+        "state": "OFF",
+        "co2": 612.0,
+        "model": "SNZB-02",
+        "voltage": 3000,
+    }));
+    let event = Event::from_mqtt(&payload, "kitchen", &subscription, received_at());
+
+    let expected = expected_event("kitchen")
+        .with_field("temperature", FieldValue::Float(21.0))
+        .with_field("state", FieldValue::Boolean(false))
+        .with_field("co2", FieldValue::Integer(612))
+        .with_tag("model", "SNZB-02");
+    assert_eq!(event, expected, "Only mapped keys should be kept");
+}
+
+#[test]
+fn ignored_keys_are_dropped() {
+    let subscription = Subscription {
+        ignore: vec!["last_seen".to_string()],
+        tag_keys: vec!["battery_low".to_string()],
+        ..subscription()
+    };
+    let payload = payload(json!({"power": 12.3, "last_seen": "2021-01-01T00:00:00Z", "battery_low": false}));
+    let event = Event::from_mqtt(&payload, "plug", &subscription, received_at());
+
+    let expected = expected_event("plug")
+        .with_field("power", FieldValue::Float(12.3))
+        .with_tag("battery_low", "false");
+    assert_eq!(event, expected, "Ignored keys should not be present");
+}
+
+#[test]
+fn values_that_do_not_match_the_type_are_skipped() {
+    let subscription = Subscription {
+        fields: vec![("battery".to_string(), FieldType::Integer)].into_iter().collect(),
+        ..subscription()
+    };
+    let payload = payload(json!({"battery": "low"}));
+    let event = Event::from_mqtt(&payload, "sensor", &subscription, received_at());
+    assert!(event.fields.is_empty(), "Field should be skipped");
+}
+
+fn assert_config_error(subscription: Subscription, message: &str) {
+    let e = subscription.validate().expect_err(message);
+    assert!(
+        matches!(e.downcast_ref::<AppError>(), Some(AppError::Config(_))),
+        "{} should be a config error: {:?}",
+        message,
+        e
+    );
+}
+
+#[test]
+fn overlapping_mappings_are_rejected() {
+    let subscription = Subscription {
+        fields: vec![("battery".to_string(), FieldType::Integer)].into_iter().collect(),
+        ignore: vec!["battery".to_string()],
+        ..subscription()
+    };
+    assert_config_error(subscription, "Field cannot be ignored");
+
+    let subscription = Subscription {
+        tag_keys: vec!["model".to_string()],
+        ignore: vec!["model".to_string()],
+        ..self::subscription()
+    };
+    assert_config_error(subscription, "Tag cannot be ignored");
+}
+
+#[test]
+fn subscription_settings_are_copied_to_events() {
+    let subscription = Subscription {
+        tag_keys: vec!["model".to_string()],
+        measurement: Some("climate".to_string()),
+        tags: vec![("room".to_string(), "kitchen".to_string())].into_iter().collect(),
+        retention_policy: Some("one_week".to_string()),
+        ..subscription()
+    };
+    let payload = payload(json!({"temperature": 21.5, "model": "WSDCGQ11LM"}));
+    let event = Event::from_mqtt(&payload, "kitchen", &subscription, received_at());
+    let expected = expected_event("kitchen")
+        .with_field("temperature", FieldValue::Float(21.5))
+        .with_tag("model", "WSDCGQ11LM")
+        .with_tag("room", "kitchen")
+        .with_measurement("climate")
+        .with_retention_policy("one_week");
+    assert_eq!(event, expected, "Event should match");
+}
+
+#[test]
+fn invalid_subscription_settings_are_rejected() {
+    let subscription = Subscription {
+        tag_keys: vec!["room".to_string()],
+        tags: vec![("room".to_string(), "kitchen".to_string())].into_iter().collect(),
+        ..self::subscription()
+    };
+    assert_config_error(subscription, "Static tag cannot be read from the payload");
+
+    let subscription = Subscription {
+        tags: vec![("device_name".to_string(), "kitchen".to_string())].into_iter().collect(),
+        ..self::subscription()
+    };
+    assert_config_error(subscription, "Static tag cannot be the device name");
+
+    let subscription = Subscription {
+        tag_keys: vec!["device_name".to_string()],
+        ..self::subscription()
+    };
+    assert_config_error(subscription, "Payload tag cannot be the device name");
+
+    let subscription = Subscription {
+        retention_policy: Some("one week".to_string()),
+        ..self::subscription()
+    };
+    assert_config_error(subscription, "Retention policy name should be rejected");
+
+    let subscription = Subscription {
+        measurement: Some(String::new()),
+        ..self::subscription()
+    };
+    assert_config_error(subscription, "Measurement cannot be empty");
+
+    let subscription = Subscription {
+        max_silence_ms: Some(0),
+        ..self::subscription()
+    };
+    assert_config_error(subscription, "Max silence cannot be 0");
+
+    let subscription = Subscription {
+        topic: "sensors/#/room".to_string(),
+        ..self::subscription()
+    };
+    assert_config_error(subscription, "Topic filter should be rejected");
+
+    let subscription = Subscription {
+        qos: 3,
+        ..self::subscription()
+    };
+    assert_config_error(subscription, "QoS cannot be over 2");
+}
+
+#[test]
+fn reception_time_is_used_without_time_field() {
+    let payload = payload(json!({"temperature": 21.5, "last_seen": "2021-03-01T09:59:58Z"}));
+    let event = Event::from_mqtt(&payload, "kitchen", &subscription(), received_at());
+    assert_eq!(event.time(), received_at(), "Time should be the reception time");
+}
+
+#[test]
+fn time_field_is_parsed_in_every_format() {
+    let expected = Utc.with_ymd_and_hms(2021, 3, 1, 9, 59, 58).unwrap();
+    let cases = vec![
+        (TimeFormat::Iso8601, json!("2021-03-01T10:59:58+01:00")),
+        (TimeFormat::Iso8601, json!("2021-03-01T09:59:58.000Z")),
+        (TimeFormat::EpochS, json!(expected.timestamp())),
+        (TimeFormat::EpochS, json!(expected.timestamp() as f64)),
+        (TimeFormat::EpochMs, json!(expected.timestamp_millis())),
+    ];
+    for (time_format, value) in cases {
+        let subscription = Subscription {
+            time_field: Some("last_seen".to_string()),
+            time_format,
+            ..subscription()
+        };
+        let payload = payload(json!({"temperature": 21.5, "last_seen": value}));
+        let event = Event::from_mqtt(&payload, "kitchen", &subscription, received_at());
+
+        assert_eq!(event.time(), expected, "Time should match for {:?} [{}]", time_format, value);
+        assert_eq!(event.received_at, received_at(), "Reception time should be kept");
+        assert!(event.field("last_seen").is_none(), "Time field should not be stored as a field");
+    }
+}
+
+#[test]
+fn invalid_time_field_falls_back_to_reception_time() {
+    let subscription = Subscription {
+        time_field: Some("last_seen".to_string()),
+        time_format: TimeFormat::EpochMs,
+        ..subscription()
+    };
+    let payload = payload(json!({"temperature": 21.5, "last_seen": "yesterday"}));
+    let event = Event::from_mqtt(&payload, "kitchen", &subscription, received_at());
+    assert_eq!(event.time(), received_at(), "Time should be the reception time");
+}
@@ -0,0 +1,174 @@
+use crate::test_tools::*;
+use mqtt2influx_core::{EventSink, InfluxDbApi, InfluxDbBufferParameters, InfluxDbConnectionParameters, InfluxDbSink};
+use std::time::Duration;
+
+async fn buffered_sink(server: &MockInfluxServer, dir: &str, max_bytes: u64) -> InfluxDbSink {
+    InfluxDbSink::new(InfluxDbConnectionParameters {
+        buffer: Some(InfluxDbBufferParameters {
+            dir,
+            max_bytes,
+            replay_interval: Duration::from_millis(20),
+        }),
+        ..influx_parameters(&server.url())
+    })
+    .await
+    .expect("Error creating sink")
+}
+
+fn buffered_files(dir: &str) -> usize {
+    std::fs::read_dir(dir).expect("Error reading buffer dir").count()
+}
+
+/// Values of `n` written successfully, in order.
+async fn written_numbers(server: &MockInfluxServer) -> Vec<String> {
+    server
+        .writes()
+        .await
+        .iter()
+        .filter(|w| w.response_status < 300)
+        .flat_map(|w| w.body.lines().map(str::to_string).collect::<Vec<_>>())
+        .filter_map(|line| line.split(' ').nth(1).map(str::to_string))
+        .collect()
+}
+
+#[tokio::test]
+async fn points_are_replayed_in_order_once_influx_is_back() {
+    let server = MockInfluxServer::start().await;
+    let dir = temp_dir_path("buffer");
+    let sink = buffered_sink(&server, &dir, 1024 * 1024).await;
+
+    server.set_write_status(503).await;
+    sink.sink(numbered_event(0)).await.expect("Failed points should be buffered");
+    sink.sink(numbered_event(1)).await.expect("Points should queue behind the buffer");
+    assert_eq!(buffered_files(&dir), 2, "Both points should be on disk");
+    let sent = server.writes().await.iter().filter(|w| w.body.contains(" n=1i ")).count();
+    assert_eq!(sent, 0, "Second point should not be sent while there are buffered points");
+
+    server.set_write_status(204).await;
+    wait_until("the buffer to be replayed", || async { buffered_files(&dir) == 0 }).await;
+    assert_eq!(
+        written_numbers(&server).await,
+        vec!["n=0i", "n=1i"],
+        "Points should be replayed in order"
+    );
+}
+
+#[tokio::test]
+async fn buffered_sinks_start_while_influx_is_down() {
+    let server = MockInfluxServer::start().await;
+    let dir = temp_dir_path("buffer");
+    server.set_write_status(503).await;
+    let sink = buffered_sink(&server, &dir, 1024 * 1024).await;
+
+    sink.sink(numbered_event(0))
+        .await
+        .expect("Points should be buffered while InfluxDB is down");
+    assert_eq!(buffered_files(&dir), 1);
+
+    server.set_write_status(204).await;
+    wait_until("the buffer to be replayed", || async { buffered_files(&dir) == 0 }).await;
+    assert_eq!(written_numbers(&server).await, vec!["n=0i"]);
+}
+
+#[tokio::test]
+async fn buffered_points_survive_restarts() {
+    let server = MockInfluxServer::start().await;
+    let dir = temp_dir_path("buffer");
+
+    let sink = buffered_sink(&server, &dir, 1024 * 1024).await;
+    server.set_write_status(503).await;
+    sink.sink(numbered_event(0)).await.expect("Failed points should be buffered");
+    drop(sink);
+    assert_eq!(buffered_files(&dir), 1, "Point should be on disk");
+    // Give the replay task of the dropped sink time to notice it is gone
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    server.set_write_status(204).await;
+    let _sink = buffered_sink(&server, &dir, 1024 * 1024).await;
+    wait_until("the buffer to be replayed", || async { buffered_files(&dir) == 0 }).await;
+    assert_eq!(written_numbers(&server).await, vec!["n=0i"], "Point should be replayed");
+}
+
+#[tokio::test]
+async fn partially_written_batches_are_removed_on_open() {
+    let server = MockInfluxServer::start().await;
+    let dir = temp_dir_path("buffer");
+    std::fs::create_dir_all(&dir).expect("Error creating buffer dir");
+    std::fs::write(format!("{}/00000000000000000000.tmp", dir), "kitchen n=0i").expect("Error writing partial batch");
+
+    let _sink = buffered_sink(&server, &dir, 1024 * 1024).await;
+    assert_eq!(buffered_files(&dir), 0, "Partial batch should be removed");
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert!(server.writes().await.is_empty(), "Partial batch should not be replayed");
+}
+
+#[tokio::test]
+async fn oldest_points_are_evicted_when_the_buffer_is_full() {
+    let server = MockInfluxServer::start().await;
+    let dir = temp_dir_path("buffer");
+    // Each point takes around 50 bytes, so only two of them fit
+    let sink = buffered_sink(&server, &dir, 120).await;
+
+    server.set_write_status(503).await;
+    for n in 0..4 {
+        sink.sink(numbered_event(n)).await.expect("Failed points should be buffered");
+    }
+    assert_eq!(buffered_files(&dir), 2, "Only two points should fit");
+
+    server.set_write_status(204).await;
+    wait_until("the buffer to be replayed", || async { buffered_files(&dir) == 0 }).await;
+    let numbers = written_numbers(&server).await;
+    assert_eq!(numbers, vec!["n=2i", "n=3i"], "Only the newest points should be replayed");
+}
+
+#[tokio::test]
+async fn rejected_points_are_not_buffered() {
+    let server = MockInfluxServer::start().await;
+    let dir = temp_dir_path("buffer");
+    let sink = buffered_sink(&server, &dir, 1024 * 1024).await;
+
+    server.set_write_status(400).await;
+    assert!(sink.sink(numbered_event(0)).await.is_err(), "Rejected points should fail");
+    assert_eq!(buffered_files(&dir), 0, "Rejected points should not be buffered");
+}
+
+#[tokio::test]
+async fn buffered_points_keep_their_retention_policy() {
+    let server = MockInfluxServer::start().await;
+    let dir = temp_dir_path("buffer");
+    let sink = InfluxDbSink::new(InfluxDbConnectionParameters {
+        api: InfluxDbApi::V1 {
+            db: "sensors",
+            credentials: None,
+        },
+        buffer: Some(InfluxDbBufferParameters {
+            dir: &dir,
+            max_bytes: 1024 * 1024,
+            replay_interval: Duration::from_millis(20),
+        }),
+        ..influx_parameters(&server.url())
+    })
+    .await
+    .expect("Error creating sink");
+
+    server.set_write_status(503).await;
+    sink.sink(numbered_event(0).with_retention_policy("one_week"))
+        .await
+        .expect("Failed points should be buffered");
+    sink.sink(numbered_event(1)).await.expect("Points should queue behind the buffer");
+
+    server.set_write_status(204).await;
+    wait_until("the buffer to be replayed", || async { buffered_files(&dir) == 0 }).await;
+    let paths = server
+        .writes()
+        .await
+        .into_iter()
+        .filter(|w| w.response_status < 300)
+        .map(|w| w.path)
+        .collect::<Vec<_>>();
+    assert_eq!(
+        paths,
+        vec!["/write?db=sensors&precision=ns&rp=one_week", "/write?db=sensors&precision=ns"],
+        "Retention policies should be kept"
+    );
+}
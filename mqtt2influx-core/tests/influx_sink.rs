@@ -1,6 +1,13 @@
-use influxdb::ReadQuery;
-use mqtt2influx_core::utils::{generate_random_number, generate_random_token};
-use mqtt2influx_core::{Event, EventSink, InfluxDbConnectionParameters, InfluxDbCredentials, InfluxDbSink};
+use crate::test_tools::{influx_parameters, numbered_event, MockInfluxServer};
+use influxdb::{Query, ReadQuery, WriteQuery};
+use mqtt2influx_core::chrono::{self, TimeZone, Utc};
+use mqtt2influx_core::sink::influx::{event_query, READINGS_TABLE};
+use mqtt2influx_core::utils::{generate_random_number, generate_random_token, Backoff};
+use mqtt2influx_core::{
+    Event, EventSink, FieldValue, InfluxDbApi, InfluxDbBatchParameters, InfluxDbConnectionParameters, InfluxDbCredentials, InfluxDbSink,
+    RetryParameters, SinkError,
+};
+use std::time::Duration;
 
 lazy_static::lazy_static! {
     static ref INFLUX_URL: String = {
@@ -44,36 +51,45 @@ struct InfluxSerie {
 async fn sink_sends_event() {
     let sink = InfluxDbSink::new(InfluxDbConnectionParameters {
         server: &INFLUX_URL,
-        db: &INFLUX_DB,
-        credentials: Some(InfluxDbCredentials {
-            username: &INFLUX_USER,
-            password: &INFLUX_PASSWORD,
-        }),
+        api: InfluxDbApi::V1 {
+            db: &INFLUX_DB,
+            credentials: Some(InfluxDbCredentials {
+                username: &INFLUX_USER,
+                password: &INFLUX_PASSWORD,
+            }),
+        },
+        measurement: READINGS_TABLE,
+        retention_policy: None,
+        exclude_fields: &[],
+        batch: InfluxDbBatchParameters::default(),
+        retry: None,
+        buffer: None,
     })
     .await
     .expect("Error creating sink");
 
     let name = generate_random_token(10);
 
-    let event = Event {
-        device_name: name.clone(),
-        battery: generate_random_number(1, 100) as u8,
-        humidity: generate_random_number(1, 100) as f32,
-        temperature: generate_random_number(1, 100) as f32,
-        voltage: generate_random_number(1, 100) as u16,
-        linkquality: Some(generate_random_number(1, 100) as u8),
-    };
+    let battery = generate_random_number(1, 100);
+    let humidity = generate_random_number(1, 100) as f64;
+    let temperature = generate_random_number(1, 100) as f64;
+    let event = Event::new(&name)
+        .with_field("battery", FieldValue::Integer(battery as i64))
+        .with_field("humidity", FieldValue::Float(humidity))
+        .with_field("temperature", FieldValue::Float(temperature))
+        .with_field("voltage", FieldValue::Integer(generate_random_number(1, 100) as i64))
+        .with_field("linkquality", FieldValue::Integer(generate_random_number(1, 100) as i64));
     sink.sink(event.clone()).await.expect("Should be able to sink");
 
     let client = influxdb::Client::new(INFLUX_URL.as_str(), INFLUX_DB.as_str());
-    let table = mqtt2influx_core::sink::influx::READINGS_TABLE;
+    let table = READINGS_TABLE;
 
     let query = format!(
         "SELECT device_name,temperature FROM {table} WHERE battery={battery} AND humidity={humidity} AND temperature={temperature};",
         table = table,
-        battery = event.battery,
-        humidity = event.humidity,
-        temperature = event.temperature
+        battery = battery,
+        humidity = humidity,
+        temperature = temperature
     );
     let q = ReadQuery::new(query);
 
@@ -101,3 +117,356 @@ async fn sink_sends_event() {
         _ => panic!("Value should be a String"),
     }
 }
+
+fn line_protocol(query: WriteQuery) -> String {
+    query.build().expect("Error building query").get()
+}
+
+#[test]
+fn query_contains_every_field() {
+    let event = Event::new("kitchen")
+        .with_field("battery", FieldValue::Integer(90))
+        .with_field("linkquality", FieldValue::Integer(120))
+        .with_field("temperature", FieldValue::Float(21.5))
+        .with_field("contact", FieldValue::Boolean(true))
+        .with_field("state", FieldValue::String("ON".to_string()));
+
+    let query = event_query(event, &[]).expect("Query should be built");
+    let line = line_protocol(query);
+    assert!(
+        line.starts_with(r#"readings,device_name=kitchen battery=90i,contact=true,linkquality=120i,state="ON",temperature=21.5 "#),
+        "Unexpected line protocol: {}",
+        line
+    );
+}
+
+#[test]
+fn query_skips_excluded_and_missing_fields() {
+    let event = Event::new("kitchen")
+        .with_field("battery", FieldValue::Integer(90))
+        .with_field("voltage", FieldValue::Integer(3000));
+
+    let query = event_query(event.clone(), &["voltage".to_string()]).expect("Query should be built");
+    let line = line_protocol(query);
+    assert!(line.contains(" battery=90i "), "Unexpected line protocol: {}", line);
+    assert!(!line.contains("voltage"), "Voltage should be excluded: {}", line);
+    assert!(!line.contains("linkquality"), "Missing fields should not be written: {}", line);
+
+    let excluded = vec!["battery".to_string(), "voltage".to_string()];
+    assert!(
+        event_query(event, &excluded).is_none(),
+        "Events without fields should not be written"
+    );
+}
+
+fn v2_parameters<'a>(server: &'a str, token: &'a str) -> InfluxDbConnectionParameters<'a> {
+    InfluxDbConnectionParameters {
+        api: InfluxDbApi::V2 {
+            org: "my org",
+            bucket: "sensors",
+            token,
+        },
+        ..influx_parameters(server)
+    }
+}
+
+#[tokio::test]
+async fn v2_sink_checks_the_health_of_influxdb_3() {
+    let server = MockInfluxServer::start_v3("secret-token").await;
+    let url = server.url();
+
+    InfluxDbSink::new(v2_parameters(&url, "secret-token"))
+        .await
+        .expect("Plain text health should pass");
+    let requests = server.requests().await;
+    assert_eq!(requests[0].path, "/health", "Should check health");
+    assert_eq!(
+        requests[0].headers.get("authorization").map(String::as_str),
+        Some("Token secret-token"),
+        "Health check should send the token"
+    );
+
+    let res = InfluxDbSink::new(v2_parameters(&url, "wrong-token")).await;
+    assert!(res.is_err(), "Rejected health check should fail");
+}
+
+#[tokio::test]
+async fn v2_sink_writes_to_the_v2_api() {
+    let server = MockInfluxServer::start().await;
+    let sink = InfluxDbSink::new(v2_parameters(&server.url(), "secret-token"))
+        .await
+        .expect("Error creating sink");
+
+    let event = Event::new("kitchen").with_field("temperature", FieldValue::Float(21.5));
+    sink.sink(event).await.expect("Should be able to sink");
+
+    let requests = server.requests().await;
+    assert_eq!(requests[0].path, "/health", "Should check health first");
+
+    let writes = server.writes().await;
+    assert_eq!(writes.len(), 1, "Should have written once");
+    let write = &writes[0];
+    assert_eq!(write.method, "POST", "Method should match");
+    assert_eq!(
+        write.path, "/api/v2/write?org=my+org&bucket=sensors&precision=ns",
+        "Path should match"
+    );
+    assert_eq!(
+        write.headers.get("authorization").map(String::as_str),
+        Some("Token secret-token"),
+        "Authorization should match"
+    );
+    assert!(
+        write.body.starts_with("readings,device_name=kitchen temperature=21.5 "),
+        "Unexpected body: {}",
+        write.body
+    );
+}
+
+#[tokio::test]
+async fn v1_sink_writes_to_the_v1_api() {
+    let server = MockInfluxServer::start().await;
+    let sink = InfluxDbSink::new(InfluxDbConnectionParameters {
+        api: InfluxDbApi::V1 {
+            db: "sensors",
+            credentials: Some(InfluxDbCredentials {
+                username: "user",
+                password: "password",
+            }),
+        },
+        ..influx_parameters(&server.url())
+    })
+    .await
+    .expect("Error creating sink");
+
+    let event = Event::new("kitchen").with_field("temperature", FieldValue::Float(21.5));
+    sink.sink(event).await.expect("Should be able to sink");
+
+    let requests = server.requests().await;
+    assert_eq!(requests[0].path, "/ping", "Should ping first");
+
+    let writes = server.writes().await;
+    assert_eq!(writes.len(), 1, "Should have written once");
+    assert_eq!(writes[0].path, "/write?db=sensors&precision=ns", "Path should match");
+    assert_eq!(
+        writes[0].headers.get("authorization").map(String::as_str),
+        Some("Basic dXNlcjpwYXNzd29yZA=="),
+        "Authorization should match"
+    );
+}
+
+#[tokio::test]
+async fn sink_fails_to_start_without_server() {
+    let res = InfluxDbSink::new(influx_parameters("http://127.0.0.1:1")).await;
+    assert!(res.is_err(), "Health check should fail");
+}
+
+fn batching_parameters(server: &str, batch_size: usize) -> InfluxDbConnectionParameters<'_> {
+    InfluxDbConnectionParameters {
+        batch: InfluxDbBatchParameters {
+            batch_size,
+            flush_interval: Duration::from_secs(3600),
+        },
+        ..influx_parameters(server)
+    }
+}
+
+async fn batching_sink(server: &MockInfluxServer, batch_size: usize, flush_interval: Duration) -> InfluxDbSink {
+    let url = server.url();
+    let mut params = batching_parameters(&url, batch_size);
+    params.batch.flush_interval = flush_interval;
+    InfluxDbSink::new(params).await.expect("Error creating sink")
+}
+
+#[tokio::test]
+async fn full_batches_are_written_in_one_request() {
+    let server = MockInfluxServer::start().await;
+    let sink = batching_sink(&server, 3, Duration::from_secs(3600)).await;
+
+    for n in 0..4 {
+        sink.sink(numbered_event(n)).await.expect("Should be able to sink");
+    }
+
+    let writes = server.writes().await;
+    assert_eq!(writes.len(), 1, "Only the full batch should have been written");
+    let lines = writes[0].body.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 3, "Batch should contain 3 points");
+    assert!(lines[0].contains(" n=0i "), "Points should keep their order: {:?}", lines);
+    assert!(lines[2].contains(" n=2i "), "Points should keep their order: {:?}", lines);
+}
+
+#[tokio::test]
+async fn failed_batches_are_retried_whole() {
+    let server = MockInfluxServer::start().await;
+    let sink = InfluxDbSink::new(InfluxDbConnectionParameters {
+        retry: Some(RetryParameters {
+            max_attempts: 3,
+            backoff: Backoff {
+                initial_delay: Duration::from_millis(1),
+                max_delay: Duration::from_millis(5),
+                multiplier: 2.0,
+                jitter: 0.0,
+            },
+            ..Default::default()
+        }),
+        ..batching_parameters(&server.url(), 3)
+    })
+    .await
+    .expect("Error creating sink");
+
+    server.fail_next_writes(1, 503).await;
+    for n in 0..3 {
+        sink.sink(numbered_event(n)).await.expect("Batch should be written on retry");
+    }
+
+    let writes = server.writes().await;
+    let statuses = writes.iter().map(|w| w.response_status).collect::<Vec<_>>();
+    assert_eq!(statuses, vec![503, 204], "Batch should be written on the second attempt");
+    assert_eq!(writes[1].body.lines().count(), 3, "Every point of the batch should be written");
+}
+
+#[tokio::test]
+async fn partial_batches_are_written_after_the_interval() {
+    let server = MockInfluxServer::start().await;
+    let sink = batching_sink(&server, 100, Duration::from_millis(50)).await;
+
+    sink.sink(numbered_event(0)).await.expect("Should be able to sink");
+    sink.sink(numbered_event(1)).await.expect("Should be able to sink");
+    assert!(server.writes().await.is_empty(), "Nothing should be written yet");
+
+    let wait = async {
+        while server.writes().await.is_empty() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    };
+    tokio::time::timeout(Duration::from_secs(5), wait)
+        .await
+        .expect("Timeout waiting for the batch");
+    let writes = server.writes().await;
+    assert_eq!(writes[0].body.lines().count(), 2, "Batch should contain 2 points");
+}
+
+#[tokio::test]
+async fn pending_batch_is_written_on_flush() {
+    let server = MockInfluxServer::start().await;
+    let sink = batching_sink(&server, 100, Duration::from_secs(3600)).await;
+
+    sink.sink(numbered_event(0)).await.expect("Should be able to sink");
+    sink.flush().await.expect("Should be able to flush");
+    sink.flush().await.expect("Flushing an empty batch should succeed");
+
+    let writes = server.writes().await;
+    assert_eq!(writes.len(), 1, "Pending batch should have been written once");
+    assert_eq!(writes[0].body.lines().count(), 1, "Batch should contain 1 point");
+}
+
+#[tokio::test]
+async fn write_failures_are_reported_with_their_kind() {
+    let server = MockInfluxServer::start().await;
+    let sink = batching_sink(&server, 1, Duration::from_secs(1)).await;
+
+    let cases = vec![
+        (400, SinkError::from_status(400, String::new()), false),
+        (401, SinkError::from_status(401, String::new()), false),
+        (429, SinkError::from_status(429, String::new()), true),
+        (503, SinkError::from_status(503, String::new()), true),
+        (0, SinkError::Network(String::new()), true),
+    ];
+    for (status, expected, retryable) in cases {
+        server.set_write_status(status).await;
+        let e = sink.sink(numbered_event(0)).await.expect_err("Sink should fail");
+        let sink_error = SinkError::find(&e).expect("Should be a SinkError");
+        assert_eq!(
+            std::mem::discriminant(sink_error),
+            std::mem::discriminant(&expected),
+            "Unexpected error for status [{}]: {:?}",
+            status,
+            sink_error
+        );
+        assert_eq!(
+            sink_error.is_retryable(),
+            retryable,
+            "Retryable should match for status [{}]",
+            status
+        );
+    }
+
+    server.set_write_status(204).await;
+    sink.sink(numbered_event(0)).await.expect("Sink should recover");
+}
+
+#[test]
+fn query_uses_the_event_time() {
+    let received_at = Utc.with_ymd_and_hms(2021, 3, 1, 10, 0, 0).unwrap();
+    let event = Event::new("kitchen")
+        .with_field("temperature", FieldValue::Float(21.5))
+        .with_received_at(received_at);
+    let line = line_protocol(event_query(event.clone(), &[]).expect("Query should be built"));
+    assert!(
+        line.ends_with(&format!(" {}", received_at.timestamp_nanos())),
+        "Reception time should be used: {}",
+        line
+    );
+
+    let time = received_at - chrono::Duration::seconds(30);
+    let line = line_protocol(event_query(event.with_time(time), &[]).expect("Query should be built"));
+    assert!(
+        line.ends_with(&format!(" {}", time.timestamp_nanos())),
+        "Payload time should be used: {}",
+        line
+    );
+}
+
+#[test]
+fn query_uses_the_event_measurement_and_tags() {
+    let event = Event::new("kitchen")
+        .with_field("temperature", FieldValue::Float(21.5))
+        .with_tag("room", "kitchen")
+        .with_tag("floor", "0");
+    let line = line_protocol(event_query(event.clone(), &[]).expect("Query should be built"));
+    assert!(line.starts_with("readings,"), "Default measurement should be used: {}", line);
+
+    let line = line_protocol(event_query(event.with_measurement("climate"), &[]).expect("Query should be built"));
+    assert!(line.starts_with("climate,"), "Event measurement should be used: {}", line);
+    for tag in &["device_name=kitchen", "room=kitchen", "floor=0"] {
+        assert!(line.contains(tag), "Tag [{}] should be written: {}", tag, line);
+    }
+}
+
+#[tokio::test]
+async fn sink_writes_each_retention_policy_in_its_own_request() {
+    let server = MockInfluxServer::start().await;
+    let sink = InfluxDbSink::new(InfluxDbConnectionParameters {
+        api: InfluxDbApi::V1 {
+            db: "sensors",
+            credentials: None,
+        },
+        measurement: "climate",
+        retention_policy: Some("one_week"),
+        ..batching_parameters(&server.url(), 3)
+    })
+    .await
+    .expect("Error creating sink");
+
+    sink.sink(numbered_event(0)).await.expect("Should be able to sink");
+    sink.sink(numbered_event(1).with_retention_policy("forever").with_measurement("energy"))
+        .await
+        .expect("Should be able to sink");
+    sink.sink(numbered_event(2)).await.expect("Should be able to sink");
+
+    let writes = server.writes().await;
+    assert_eq!(writes.len(), 2, "Each retention policy should have its own request");
+    assert_eq!(writes[0].path, "/write?db=sensors&precision=ns&rp=one_week", "Path should match");
+    assert_eq!(writes[0].body.lines().count(), 2, "Default retention policy should get 2 points");
+    assert!(
+        writes[0].body.starts_with("climate,"),
+        "Default measurement should be used: {}",
+        writes[0].body
+    );
+    assert_eq!(writes[1].path, "/write?db=sensors&precision=ns&rp=forever", "Path should match");
+    assert!(
+        writes[1].body.starts_with("energy,"),
+        "Event measurement should be used: {}",
+        writes[1].body
+    );
+}
@@ -1,4 +1,15 @@
 pub mod test_tools;
 
 mod basic;
+mod device_monitor;
+mod event;
+mod influx_buffer;
 mod influx_sink;
+mod metrics;
+mod mqtt_source;
+mod queued_sink;
+mod retry_sink;
+mod sink_filter;
+mod sink_registry;
+mod sink_set;
+mod topic;
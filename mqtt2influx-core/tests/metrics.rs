@@ -0,0 +1,95 @@
+use crate::test_tools::*;
+use mqtt2influx_core::serde_json::Value;
+use mqtt2influx_core::{Event, EventSink, Executor, Metrics, QueueStats, SinkRegistry, SinkSetPolicy, SinkSpec};
+use std::sync::Arc;
+use std::time::Duration;
+
+#[test]
+fn metrics_are_rendered_in_the_text_format() {
+    let metrics = Metrics::default();
+    metrics.message_received("sensors/kitchen");
+    metrics.message_received("sensors/kitchen");
+    metrics.message_received("sensors/\"quoted\"");
+    metrics.parse_failed("sensors/kitchen");
+    metrics.set_mqtt_connected(true);
+    metrics.mqtt_reconnecting();
+
+    let rendered = metrics.render(&[QueueStats {
+        name: "influx".to_string(),
+        depth: 3,
+        capacity: 10,
+        dropped: 1,
+        failed: 0,
+    }]);
+    let lines = rendered.lines().collect::<Vec<_>>();
+    for expected in &[
+        "# TYPE mqtt2influx_messages_received_total counter",
+        r#"mqtt2influx_messages_received_total{topic="sensors/kitchen"} 2"#,
+        r#"mqtt2influx_messages_received_total{topic="sensors/\"quoted\""} 1"#,
+        r#"mqtt2influx_parse_failures_total{topic="sensors/kitchen"} 1"#,
+        "mqtt2influx_mqtt_connected 1",
+        "mqtt2influx_mqtt_reconnects_total 1",
+        "mqtt2influx_channel_depth 0",
+        r#"mqtt2influx_sink_queue_depth{sink="influx"} 3"#,
+        r#"mqtt2influx_sink_queue_dropped_total{sink="influx"} 1"#,
+    ] {
+        assert!(lines.contains(expected), "Missing [{}] in:\n{}", expected, rendered);
+    }
+}
+
+#[test]
+fn sink_latency_buckets_are_cumulative() {
+    let metrics = Metrics::default();
+    metrics.sink_called("influx", Duration::from_millis(3), false);
+    metrics.sink_called("influx", Duration::from_millis(200), true);
+    metrics.sink_called("influx", Duration::from_secs(30), false);
+
+    let rendered = metrics.render(&[]);
+    let lines = rendered.lines().collect::<Vec<_>>();
+    for expected in &[
+        r#"mqtt2influx_sink_duration_seconds_bucket{sink="influx",le="0.001"} 0"#,
+        r#"mqtt2influx_sink_duration_seconds_bucket{sink="influx",le="0.005"} 1"#,
+        r#"mqtt2influx_sink_duration_seconds_bucket{sink="influx",le="0.25"} 2"#,
+        r#"mqtt2influx_sink_duration_seconds_bucket{sink="influx",le="10"} 2"#,
+        r#"mqtt2influx_sink_duration_seconds_bucket{sink="influx",le="+Inf"} 3"#,
+        r#"mqtt2influx_sink_duration_seconds_count{sink="influx"} 3"#,
+        r#"mqtt2influx_sink_errors_total{sink="influx"} 1"#,
+    ] {
+        assert!(lines.contains(expected), "Missing [{}] in:\n{}", expected, rendered);
+    }
+}
+
+#[tokio::test]
+async fn executor_and_sinks_are_measured() {
+    let mut registry = SinkRegistry::default();
+    registry.register("failing", |_| async {
+        Ok(Arc::new(FailingEventSink::failing_for("broken")) as Arc<dyn EventSink>)
+    });
+    let metrics = Metrics::default();
+    let spec = SinkSpec {
+        name: "failing".to_string(),
+        sink_type: "failing".to_string(),
+        options: Value::Null,
+        retry: None,
+        queue: None,
+        filter: None,
+    };
+    let graph = registry
+        .with_metrics(metrics.clone())
+        .build(vec![spec], SinkSetPolicy::All)
+        .await
+        .expect("Should build the sinks");
+
+    let events = vec![random_event(), Event::new("broken"), random_event()];
+    let source = MockEventSource { events };
+    Executor::run_with_metrics(source, &graph.sinks, &metrics)
+        .await
+        .expect("Executor should not fail");
+
+    assert_eq!(metrics.events_sunk(), 2, "Two events should be sunk");
+    assert_eq!(metrics.sink_errors("failing"), 1, "One sink error should be counted");
+    assert!(
+        metrics.render(&[]).contains("mqtt2influx_events_failed_total 1"),
+        "One failed event should be counted"
+    );
+}
@@ -0,0 +1,611 @@
+use crate::test_tools::*;
+use mqtt2influx_core::utils::Backoff;
+use mqtt2influx_core::{
+    ConnectionState, EventSource, Executor, FieldValue, Metrics, MqttClientAuth, MqttConnectionParameters, MqttCredentials,
+    MqttEventSource, MqttReconnectParameters, MqttTlsParameters, Shutdown, SinkError, SourceEvent, Subscription,
+};
+use mqttbytes::v4::Login;
+use mqttbytes::QoS;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc::Receiver;
+
+const PAYLOAD: &str = r#"{"battery":90,"humidity":45.5,"temperature":21.3,"voltage":3000,"linkquality":120}"#;
+
+fn subscriptions() -> Vec<Subscription> {
+    vec![Subscription {
+        topic: "sensors/room".to_string(),
+        device_name: "Room".to_string(),
+        qos: 0,
+        ..Default::default()
+    }]
+}
+
+fn connection(client_id: &str, port: u16) -> MqttConnectionParameters<'_> {
+    MqttConnectionParameters {
+        client_id,
+        host: "127.0.0.1",
+        port,
+        keep_alive: 60,
+        clean_session: true,
+        credentials: None,
+        tls: None,
+        reconnect: MqttReconnectParameters::default(),
+    }
+}
+
+fn fast_reconnect(max_retries: Option<u32>) -> MqttReconnectParameters {
+    MqttReconnectParameters {
+        backoff: Backoff {
+            initial_delay: Duration::from_millis(10),
+            max_delay: Duration::from_millis(50),
+            multiplier: 2.0,
+            jitter: 0.5,
+        },
+        max_retries,
+    }
+}
+
+async fn next_event(rx: &mut Receiver<SourceEvent>) -> SourceEvent {
+    tokio::time::timeout(Duration::from_secs(5), rx.recv())
+        .await
+        .expect("Timeout waiting for event")
+        .expect("Channel should not be closed")
+}
+
+#[tokio::test]
+async fn source_sends_credentials() {
+    let broker = MockMqttBroker::start(Some(("user", "password"))).await;
+    let source = MqttEventSource::new(
+        MqttConnectionParameters {
+            credentials: Some(MqttCredentials {
+                username: "user",
+                password: "password",
+            }),
+            ..connection("credentials-test", broker.port)
+        },
+        subscriptions(),
+    )
+    .expect("Error creating source");
+
+    let mut rx = source.start().await.expect("Error starting source");
+    broker.wait_for_subscriptions(1).await;
+    broker.publish("sensors/room", PAYLOAD);
+
+    let received = next_event(&mut rx).await;
+    assert_eq!(received.event.device_name, "Room", "Device name should match");
+
+    let connects = broker.connects().await;
+    assert_eq!(connects.len(), 1, "Should have connected once");
+    assert_eq!(connects[0].login, Some(Login::new("user", "password")), "Credentials should match");
+}
+
+#[tokio::test]
+async fn source_without_credentials_sends_no_login() {
+    let broker = MockMqttBroker::start(None).await;
+    let source = MqttEventSource::new(connection("anonymous-test", broker.port), subscriptions()).expect("Error creating source");
+
+    let _rx = source.start().await.expect("Error starting source");
+    broker.wait_for_subscriptions(1).await;
+
+    let connects = broker.connects().await;
+    assert_eq!(connects.len(), 1, "Should have connected once");
+    assert!(connects[0].login.is_none(), "No credentials should be sent");
+}
+
+#[tokio::test]
+async fn source_connects_with_mutual_tls() {
+    let certificates = TestCertificates::generate();
+    let broker = MockMqttBroker::start_tls(None, &certificates).await;
+    let source = MqttEventSource::new(
+        MqttConnectionParameters {
+            host: "localhost",
+            tls: Some(MqttTlsParameters {
+                ca_file: Some(&certificates.ca_file),
+                client_auth: Some(MqttClientAuth {
+                    cert_file: &certificates.client_cert_file,
+                    key_file: &certificates.client_key_file,
+                }),
+                insecure_skip_verify: false,
+            }),
+            ..connection("tls-test", broker.port)
+        },
+        subscriptions(),
+    )
+    .expect("Error creating source");
+
+    let mut rx = source.start().await.expect("Error starting source");
+    broker.wait_for_subscriptions(1).await;
+    broker.publish("sensors/room", PAYLOAD);
+
+    let received = next_event(&mut rx).await;
+    assert_eq!(received.event.device_name, "Room", "Device name should match");
+}
+
+#[tokio::test]
+async fn source_connects_with_a_sec1_client_key() {
+    let certificates = TestCertificates::generate();
+    let broker = MockMqttBroker::start_tls(None, &certificates).await;
+    let source = MqttEventSource::new(
+        MqttConnectionParameters {
+            host: "localhost",
+            tls: Some(MqttTlsParameters {
+                ca_file: Some(&certificates.ca_file),
+                client_auth: Some(MqttClientAuth {
+                    cert_file: &certificates.client_cert_file,
+                    key_file: &certificates.client_ec_key_file,
+                }),
+                insecure_skip_verify: false,
+            }),
+            ..connection("tls-ec-test", broker.port)
+        },
+        subscriptions(),
+    )
+    .expect("Error creating source");
+
+    let _rx = source.start().await.expect("Error starting source");
+    broker.wait_for_subscriptions(1).await;
+}
+
+#[test]
+fn tls_parameters_report_missing_files() {
+    let params = MqttTlsParameters {
+        ca_file: Some("/nonexistent/ca.pem"),
+        client_auth: None,
+        insecure_skip_verify: false,
+    };
+    assert!(params.validate().is_err(), "Missing CA file should fail");
+}
+
+#[test]
+fn tls_parameters_report_invalid_files() {
+    let certificates = TestCertificates::generate();
+    let garbage = write_temp_file("garbage.pem", "not a certificate");
+
+    let params = MqttTlsParameters {
+        ca_file: Some(&garbage),
+        client_auth: None,
+        insecure_skip_verify: false,
+    };
+    assert!(params.validate().is_err(), "Invalid CA file should fail");
+
+    let params = MqttTlsParameters {
+        ca_file: Some(&certificates.ca_file),
+        client_auth: Some(MqttClientAuth {
+            cert_file: &certificates.client_cert_file,
+            key_file: &garbage,
+        }),
+        insecure_skip_verify: false,
+    };
+    let err = params.validate().expect_err("Invalid client key should fail");
+    assert!(
+        format!("{:#}", err).contains("EC PRIVATE KEY"),
+        "Error should name the supported key formats [{:#}]",
+        err
+    );
+}
+
+#[tokio::test]
+async fn wildcard_subscription_names_device_from_topic() {
+    let broker = MockMqttBroker::start(None).await;
+    let source = MqttEventSource::new(
+        connection("wildcard-test", broker.port),
+        vec![Subscription {
+            topic: "zigbee2mqtt/+".to_string(),
+            device_name: "{1}".to_string(),
+            qos: 0,
+            ..Default::default()
+        }],
+    )
+    .expect("Error creating source");
+
+    let mut rx = source.start().await.expect("Error starting source");
+    broker.wait_for_subscriptions(1).await;
+    broker.publish("zigbee2mqtt/kitchen", PAYLOAD);
+    broker.publish("zigbee2mqtt/bedroom", PAYLOAD);
+
+    for expected in &["kitchen", "bedroom"] {
+        let received = next_event(&mut rx).await;
+        assert_eq!(&received.event.device_name, expected, "Device name should come from the topic");
+        assert_eq!(
+            received.event.topic,
+            Some(format!("zigbee2mqtt/{}", expected)),
+            "Topic should be kept"
+        );
+    }
+}
+
+#[tokio::test]
+async fn source_resubscribes_after_reconnect() {
+    let broker = MockMqttBroker::start(None).await;
+    let source = MqttEventSource::new(
+        MqttConnectionParameters {
+            reconnect: fast_reconnect(None),
+            ..connection("reconnect-test", broker.port)
+        },
+        subscriptions(),
+    )
+    .expect("Error creating source");
+    let state = source.connection_state();
+
+    let mut rx = source.start().await.expect("Error starting source");
+    broker.wait_for_subscriptions(1).await;
+    assert_eq!(*state.borrow(), ConnectionState::Connected, "Should be connected");
+
+    broker.disconnect_clients();
+    broker.wait_for_subscriptions(2).await;
+    assert_eq!(broker.connects().await.len(), 2, "Should have reconnected once");
+
+    broker.publish("sensors/room", PAYLOAD);
+    let received = next_event(&mut rx).await;
+    assert_eq!(received.event.device_name, "Room", "Device name should match");
+    assert_eq!(*state.borrow(), ConnectionState::Connected, "Should be connected again");
+}
+
+#[tokio::test]
+async fn source_gives_up_after_max_retries() {
+    let broker = MockMqttBroker::start(Some(("user", "password"))).await;
+    let source = MqttEventSource::new(
+        MqttConnectionParameters {
+            reconnect: fast_reconnect(Some(2)),
+            ..connection("give-up-test", broker.port)
+        },
+        subscriptions(),
+    )
+    .expect("Error creating source");
+    let state = source.connection_state();
+
+    let mut rx = source.start().await.expect("Error starting source");
+    let next = tokio::time::timeout(Duration::from_secs(5), rx.recv())
+        .await
+        .expect("Timeout waiting for the source to give up");
+    assert!(next.is_none(), "Channel should be closed");
+    assert_eq!(*state.borrow(), ConnectionState::Failed, "Should be failed");
+    assert_eq!(broker.connects().await.len(), 3, "Should have tried once plus two retries");
+}
+
+#[tokio::test]
+async fn source_sends_session_settings_and_qos() {
+    let broker = MockMqttBroker::start(None).await;
+    let source = MqttEventSource::new(
+        MqttConnectionParameters {
+            keep_alive: 30,
+            clean_session: false,
+            ..connection("session-test", broker.port)
+        },
+        vec![Subscription {
+            topic: "sensors/room".to_string(),
+            device_name: "Room".to_string(),
+            qos: 2,
+            ..Default::default()
+        }],
+    )
+    .expect("Error creating source");
+
+    let _rx = source.start().await.expect("Error starting source");
+    broker.wait_for_subscriptions(1).await;
+
+    let connects = broker.connects().await;
+    assert_eq!(connects[0].keep_alive, 30, "Keep alive should match");
+    assert!(!connects[0].clean_session, "Session should be persistent");
+    assert_eq!(
+        broker.subscriptions().await,
+        vec![("sensors/room".to_string(), QoS::ExactlyOnce)],
+        "QoS should match"
+    );
+}
+
+fn fast_backoff() -> Backoff {
+    Backoff {
+        initial_delay: Duration::from_millis(1),
+        max_delay: Duration::from_millis(5),
+        multiplier: 2.0,
+        jitter: 0.0,
+    }
+}
+
+fn qos_subscriptions() -> Vec<Subscription> {
+    vec![Subscription {
+        topic: "sensors/+".to_string(),
+        device_name: "{1}".to_string(),
+        qos: 1,
+        ..Default::default()
+    }]
+}
+
+#[tokio::test]
+async fn qos_messages_are_acked_only_after_being_sunk() {
+    let broker = MockMqttBroker::start(None).await;
+    let source = MqttEventSource::new(
+        connection("ack-test", broker.port),
+        vec![Subscription {
+            topic: "sensors/+".to_string(),
+            device_name: "{1}".to_string(),
+            qos: 1,
+            ..Default::default()
+        }],
+    )
+    .expect("Error creating source");
+
+    let sink = Arc::new(FailingEventSink::failing_for("broken"));
+    let executor_sink = sink.clone();
+    tokio::spawn(async move {
+        let _ = Executor::run(source, executor_sink.as_ref()).await;
+    });
+    broker.wait_for_subscriptions(1).await;
+
+    broker.publish_with_qos("sensors/broken", PAYLOAD, QoS::AtLeastOnce, 1);
+    broker.publish_with_qos("sensors/room", PAYLOAD, QoS::AtLeastOnce, 2);
+    broker.publish_with_qos("sensors/room", "not json", QoS::AtLeastOnce, 3);
+
+    let wait = async {
+        while broker.acks().await.len() < 2 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    };
+    tokio::time::timeout(Duration::from_secs(5), wait)
+        .await
+        .expect("Timeout waiting for acks");
+    // Give a late ack for the failed message a chance to show up
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut acks = broker.acks().await;
+    acks.sort_unstable();
+    assert_eq!(
+        acks,
+        vec![1, 2, 3],
+        "Sunk, discarded and permanently failed messages should be acked"
+    );
+    assert_eq!(sink.received().await.len(), 1, "Only one event should have been sunk");
+}
+
+#[tokio::test]
+async fn failed_events_do_not_fill_the_broker_inflight_window() {
+    let max_inflight = 20;
+    let broker = MockMqttBroker::start_with_max_inflight(max_inflight).await;
+    let source = MqttEventSource::new(connection("inflight-test", broker.port), qos_subscriptions()).expect("Error creating source");
+
+    let sink = Arc::new(FailingEventSink::failing_for("broken"));
+    let executor_sink = sink.clone();
+    tokio::spawn(async move {
+        let _ = Executor::run(source, executor_sink.as_ref()).await;
+    });
+    broker.wait_for_subscriptions(1).await;
+
+    let failed = max_inflight as u16 + 5;
+    for pkid in 1..=failed {
+        broker.publish_with_qos("sensors/broken", PAYLOAD, QoS::AtLeastOnce, pkid);
+    }
+    broker.publish_with_qos("sensors/room", PAYLOAD, QoS::AtLeastOnce, failed + 1);
+
+    wait_until("the later event", || async { !sink.received().await.is_empty() }).await;
+    wait_until("every ack", || async { broker.acks().await.len() == failed as usize + 1 }).await;
+}
+
+#[tokio::test]
+async fn events_failing_with_retryable_errors_are_retried_until_sunk() {
+    let max_inflight = 20;
+    let broker = MockMqttBroker::start_with_max_inflight(max_inflight).await;
+    let source = MqttEventSource::new(connection("retry-test", broker.port), qos_subscriptions()).expect("Error creating source");
+
+    let failures = max_inflight as u32 + 5;
+    let sink = Arc::new(FlakyEventSink::failing(
+        failures,
+        SinkError::Network("InfluxDB is down".to_string()),
+    ));
+    let executor_sink = sink.clone();
+    tokio::spawn(async move {
+        let backoff = fast_backoff();
+        let _ = Executor::run_with_retries(source, executor_sink.as_ref(), &Metrics::default(), &backoff, &Shutdown::default()).await;
+    });
+    broker.wait_for_subscriptions(1).await;
+
+    for pkid in 1..=3 {
+        broker.publish_with_qos("sensors/room", PAYLOAD, QoS::AtLeastOnce, pkid);
+    }
+
+    wait_until("every ack", || async { broker.acks().await.len() == 3 }).await;
+    assert_eq!(sink.received().await.len(), 3, "Every event should be sunk");
+    assert_eq!(sink.calls(), failures + 3, "Failed event should be retried");
+}
+
+#[tokio::test]
+async fn retries_stop_on_shutdown_without_acking() {
+    let broker = MockMqttBroker::start(None).await;
+    let shutdown = Shutdown::default();
+    let source = MqttEventSource::new(connection("retry-shutdown-test", broker.port), qos_subscriptions())
+        .expect("Error creating source")
+        .with_shutdown(shutdown.clone());
+
+    let sink = Arc::new(FlakyEventSink::failing(
+        u32::MAX,
+        SinkError::Network("InfluxDB is down".to_string()),
+    ));
+    let executor_sink = sink.clone();
+    let executor_shutdown = shutdown.clone();
+    let executor = tokio::spawn(async move {
+        let backoff = fast_backoff();
+        Executor::run_with_retries(source, executor_sink.as_ref(), &Metrics::default(), &backoff, &executor_shutdown).await
+    });
+    broker.wait_for_subscriptions(1).await;
+
+    broker.publish_with_qos("sensors/room", PAYLOAD, QoS::AtLeastOnce, 1);
+    wait_until("a few retries", || async { sink.calls() > 3 }).await;
+    shutdown.trigger();
+    tokio::time::timeout(Duration::from_secs(5), executor)
+        .await
+        .expect("Timeout waiting for the executor to stop")
+        .expect("Task should not panic")
+        .expect("Executor should not fail");
+
+    wait_until("the disconnection", || async { !broker.disconnects().await.is_empty() }).await;
+    assert_eq!(
+        broker.disconnects().await,
+        vec![Vec::<u16>::new()],
+        "Failed message should be left for the broker to redeliver"
+    );
+}
+
+#[tokio::test]
+async fn devices_without_sensor_readings_are_received() {
+    let broker = MockMqttBroker::start(None).await;
+    let source = MqttEventSource::new(
+        connection("generic-test", broker.port),
+        vec![Subscription {
+            topic: "zigbee2mqtt/+".to_string(),
+            device_name: "{1}".to_string(),
+            ..Default::default()
+        }],
+    )
+    .expect("Error creating source");
+
+    let mut rx = source.start().await.expect("Error starting source");
+    broker.wait_for_subscriptions(1).await;
+    broker.publish("zigbee2mqtt/door", "[1, 2, 3]");
+    broker.publish("zigbee2mqtt/door", r#"{"contact":false,"battery":100}"#);
+
+    let received = next_event(&mut rx).await;
+    assert_eq!(received.event.device_name, "door", "Device name should match");
+    assert_eq!(
+        received.event.field("contact"),
+        Some(&FieldValue::Boolean(false)),
+        "Contact should match"
+    );
+    assert_eq!(
+        received.event.field("battery"),
+        Some(&FieldValue::Integer(100)),
+        "Battery should match"
+    );
+}
+
+#[tokio::test]
+async fn shutdown_acks_pending_messages_before_disconnecting() {
+    let broker = MockMqttBroker::start(None).await;
+    let shutdown = Shutdown::default();
+    let source = MqttEventSource::new(
+        connection("shutdown-test", broker.port),
+        vec![Subscription {
+            topic: "sensors/room".to_string(),
+            device_name: "Room".to_string(),
+            qos: 1,
+            ..Default::default()
+        }],
+    )
+    .expect("Error creating source")
+    .with_shutdown(shutdown.clone());
+
+    let sink = Arc::new(GatedEventSink::default());
+    let executor_sink = sink.clone();
+    let executor = tokio::spawn(async move { Executor::run(source, executor_sink.as_ref()).await });
+    broker.wait_for_subscriptions(1).await;
+
+    broker.publish_with_qos("sensors/room", PAYLOAD, QoS::AtLeastOnce, 1);
+    // Let the event reach the stalled sink
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    shutdown.trigger();
+    wait_until("the unsubscription", || async { !broker.unsubscriptions().await.is_empty() }).await;
+    assert!(!executor.is_finished(), "Executor should wait for the pending event");
+    assert!(broker.disconnects().await.is_empty(), "Source should wait for the pending ack");
+
+    sink.release(1);
+    tokio::time::timeout(Duration::from_secs(5), executor)
+        .await
+        .expect("Timeout waiting for the executor to stop")
+        .expect("Task should not panic")
+        .expect("Executor should not fail");
+    assert_eq!(sink.received().await.len(), 1, "Pending event should be sunk");
+    assert_eq!(
+        broker.unsubscriptions().await,
+        vec!["sensors/room".to_string()],
+        "Should unsubscribe"
+    );
+    wait_until("the disconnection", || async { !broker.disconnects().await.is_empty() }).await;
+    assert_eq!(
+        broker.disconnects().await,
+        vec![vec![1]],
+        "Pending message should be acked before disconnecting"
+    );
+}
+
+#[tokio::test]
+async fn source_counts_messages_and_parse_failures() {
+    let broker = MockMqttBroker::start(None).await;
+    let metrics = Metrics::default();
+    let source = MqttEventSource::new(connection("metrics-test", broker.port), subscriptions())
+        .expect("Error creating source")
+        .with_metrics(metrics.clone());
+
+    let mut rx = source.start().await.expect("Error starting source");
+    broker.wait_for_subscriptions(1).await;
+    broker.publish("sensors/room", "not json");
+    broker.publish("sensors/room", PAYLOAD);
+    next_event(&mut rx).await;
+
+    assert_eq!(metrics.messages_received("sensors/room"), 2, "Both messages should be counted");
+    let rendered = metrics.render(&[]);
+    for expected in &[
+        r#"mqtt2influx_parse_failures_total{topic="sensors/room"} 1"#,
+        "mqtt2influx_mqtt_connected 1",
+        "mqtt2influx_channel_depth 1",
+    ] {
+        assert!(
+            rendered.lines().any(|line| line == *expected),
+            "Missing [{}] in:\n{}",
+            expected,
+            rendered
+        );
+    }
+}
+
+#[tokio::test]
+async fn source_resubscribes_with_acks_queued_during_the_outage() {
+    let broker = MockMqttBroker::start(None).await;
+    let source = MqttEventSource::new(
+        MqttConnectionParameters {
+            reconnect: MqttReconnectParameters {
+                backoff: Backoff {
+                    initial_delay: Duration::from_millis(300),
+                    max_delay: Duration::from_millis(300),
+                    multiplier: 1.0,
+                    jitter: 0.0,
+                },
+                max_retries: None,
+            },
+            ..connection("outage-acks-test", broker.port)
+        },
+        vec![Subscription {
+            topic: "sensors/room".to_string(),
+            device_name: "Room".to_string(),
+            qos: 1,
+            ..Default::default()
+        }],
+    )
+    .expect("Error creating source");
+    let state = source.connection_state();
+
+    let sink = Arc::new(GatedEventSink::default());
+    let executor_sink = sink.clone();
+    let executor = tokio::spawn(async move { Executor::run(source, executor_sink.as_ref()).await });
+    broker.wait_for_subscriptions(1).await;
+
+    // One event held by the sink and ten in the source channel
+    for pkid in 1..=11 {
+        broker.publish_with_qos("sensors/room", PAYLOAD, QoS::AtLeastOnce, pkid);
+    }
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    // The acks fill the request channel of the client while the broker is away
+    broker.disconnect_clients();
+    wait_until("the source to notice the outage", || async {
+        matches!(*state.borrow(), ConnectionState::Reconnecting { .. })
+    })
+    .await;
+    sink.release(12);
+    wait_until("the events to be sunk", || async { sink.received().await.len() == 11 }).await;
+
+    broker.wait_for_subscriptions(2).await;
+    assert!(!executor.is_finished(), "Source should still be running");
+    assert_eq!(*state.borrow(), ConnectionState::Connected, "Should be connected again");
+    broker.publish("sensors/room", PAYLOAD);
+    wait_until("the event after the reconnection", || async { sink.received().await.len() == 12 }).await;
+}
@@ -0,0 +1,126 @@
+use crate::test_tools::*;
+use mqtt2influx_core::{Event, EventSink, FieldValue, OverflowPolicy, QueueParameters, QueuedSink};
+use std::sync::Arc;
+use std::time::Duration;
+
+fn numbers(events: &[Event]) -> Vec<i64> {
+    events
+        .iter()
+        .filter_map(|e| match e.field("n") {
+            Some(FieldValue::Integer(n)) => Some(*n),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Queue of `capacity` events in front of a stalled sink, with the worker already holding event 0.
+async fn stalled_queue(capacity: usize, overflow: OverflowPolicy) -> (Arc<GatedEventSink>, QueuedSink) {
+    let inner = Arc::new(GatedEventSink::default());
+    let queue = QueuedSink::new("gated", inner.clone(), QueueParameters { capacity, overflow });
+    queue.sink(numbered_event(0)).await.expect("Should be able to queue");
+    wait_until("the worker to take the first event", || async { queue.depth() == 0 }).await;
+    (inner, queue)
+}
+
+#[tokio::test]
+async fn events_are_stored_in_order() {
+    let inner = Arc::new(MockEventSink::default());
+    let queue = QueuedSink::new("mock", inner.clone(), QueueParameters::default());
+
+    for n in 0..10 {
+        queue.sink(numbered_event(n)).await.expect("Should be able to queue");
+    }
+    wait_until("the events to be stored", || async { inner.received().await.len() == 10 }).await;
+    assert_eq!(
+        numbers(&inner.received().await),
+        (0..10).collect::<Vec<_>>(),
+        "Order should be kept"
+    );
+    assert_eq!(queue.depth(), 0, "Queue should be empty");
+}
+
+#[tokio::test]
+async fn stalled_sink_does_not_block_the_caller() {
+    let (inner, queue) = stalled_queue(5, OverflowPolicy::Block).await;
+
+    for n in 1..4 {
+        tokio::time::timeout(Duration::from_millis(100), queue.sink(numbered_event(n)))
+            .await
+            .expect("Queueing should not wait for the sink")
+            .expect("Should be able to queue");
+    }
+    assert_eq!(queue.depth(), 3, "Events should wait in the queue");
+
+    inner.release(4);
+    wait_until("the queue to be drained", || async { inner.received().await.len() == 4 }).await;
+    assert_eq!(queue.depth(), 0, "Queue should be empty");
+}
+
+#[tokio::test]
+async fn full_queue_blocks_the_caller() {
+    let (inner, queue) = stalled_queue(1, OverflowPolicy::Block).await;
+    let queue = Arc::new(queue);
+    queue.sink(numbered_event(1)).await.expect("Should be able to queue");
+
+    let blocked_queue = queue.clone();
+    let blocked = tokio::spawn(async move { blocked_queue.sink(numbered_event(2)).await });
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(!blocked.is_finished(), "Caller should wait for room in the queue");
+
+    inner.release(3);
+    blocked
+        .await
+        .expect("Task should not panic")
+        .expect("Should be queued once there is room");
+    wait_until("the events to be stored", || async { inner.received().await.len() == 3 }).await;
+    assert_eq!(numbers(&inner.received().await), vec![0, 1, 2], "No event should be dropped");
+    assert_eq!(queue.stats().dropped, 0, "No event should be dropped");
+}
+
+#[tokio::test]
+async fn drop_oldest_keeps_the_newest_events() {
+    let (inner, queue) = stalled_queue(2, OverflowPolicy::DropOldest).await;
+    for n in 1..4 {
+        queue.sink(numbered_event(n)).await.expect("Full queue should not fail");
+    }
+    let stats = queue.stats();
+    assert_eq!((stats.depth, stats.dropped), (2, 1), "Oldest event should be dropped");
+
+    inner.release(3);
+    wait_until("the events to be stored", || async { inner.received().await.len() == 3 }).await;
+    assert_eq!(numbers(&inner.received().await), vec![0, 2, 3], "Event 1 should be dropped");
+}
+
+#[tokio::test]
+async fn drop_newest_keeps_the_queued_events() {
+    let (inner, queue) = stalled_queue(2, OverflowPolicy::DropNewest).await;
+    for n in 1..4 {
+        queue.sink(numbered_event(n)).await.expect("Full queue should not fail");
+    }
+    let stats = queue.stats();
+    assert_eq!((stats.depth, stats.dropped), (2, 1), "Newest event should be dropped");
+
+    inner.release(3);
+    wait_until("the events to be stored", || async { inner.received().await.len() == 3 }).await;
+    assert_eq!(numbers(&inner.received().await), vec![0, 1, 2], "Event 3 should be dropped");
+}
+
+#[tokio::test]
+async fn flush_waits_for_the_queued_events() {
+    let (inner, queue) = stalled_queue(5, OverflowPolicy::Block).await;
+    let queue = Arc::new(queue);
+    queue.sink(numbered_event(1)).await.expect("Should be able to queue");
+
+    let flushed_queue = queue.clone();
+    let flushed = tokio::spawn(async move { flushed_queue.flush().await });
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(!flushed.is_finished(), "Flush should wait for the queued events");
+
+    inner.release(2);
+    tokio::time::timeout(Duration::from_secs(5), flushed)
+        .await
+        .expect("Timeout waiting for the flush")
+        .expect("Task should not panic")
+        .expect("Flush should not fail");
+    assert_eq!(numbers(&inner.received().await), vec![0, 1], "Queued events should be stored");
+}
@@ -0,0 +1,79 @@
+use crate::test_tools::*;
+use mqtt2influx_core::utils::Backoff;
+use mqtt2influx_core::{EventSink, RetryParameters, RetrySink, SinkError};
+use std::sync::Arc;
+use std::time::Duration;
+
+fn retry_parameters(max_attempts: u32) -> RetryParameters {
+    RetryParameters {
+        max_attempts,
+        backoff: Backoff {
+            initial_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(5),
+            multiplier: 2.0,
+            jitter: 0.5,
+        },
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn retryable_errors_are_retried_until_success() {
+    let inner = Arc::new(FlakyEventSink::failing(2, unavailable()));
+    let sink = RetrySink::new("flaky", inner.clone(), retry_parameters(3));
+
+    sink.sink(random_event()).await.expect("Third attempt should succeed");
+    assert_eq!(inner.calls(), 3, "Should have been called 3 times");
+    assert_eq!(inner.received().await.len(), 1, "Event should have been stored once");
+}
+
+#[tokio::test]
+async fn retries_stop_after_max_attempts() {
+    let inner = Arc::new(FlakyEventSink::failing(5, SinkError::Network("reset".to_string())));
+    let sink = RetrySink::new("flaky", inner.clone(), retry_parameters(3));
+
+    let e = sink.sink(random_event()).await.expect_err("Should give up");
+    assert_eq!(
+        SinkError::find(&e),
+        Some(&SinkError::Network("reset".to_string())),
+        "Last error should be returned"
+    );
+    assert_eq!(inner.calls(), 3, "Should have been called 3 times");
+}
+
+#[tokio::test]
+async fn permanent_errors_are_not_retried() {
+    let inner = Arc::new(FlakyEventSink::failing(
+        1,
+        SinkError::from_status(400, "field type conflict".to_string()),
+    ));
+    let sink = RetrySink::new("flaky", inner.clone(), retry_parameters(3));
+
+    assert!(sink.sink(random_event()).await.is_err(), "Should fail");
+    assert_eq!(inner.calls(), 1, "Should not have been retried");
+}
+
+#[tokio::test]
+async fn classifier_decides_what_is_retried() {
+    let inner = Arc::new(FlakyEventSink::failing(
+        1,
+        SinkError::from_status(400, "field type conflict".to_string()),
+    ));
+    let params = RetryParameters {
+        classifier: Arc::new(|_| true),
+        ..retry_parameters(3)
+    };
+    let sink = RetrySink::new("flaky", inner.clone(), params);
+
+    sink.sink(random_event()).await.expect("Second attempt should succeed");
+    assert_eq!(inner.calls(), 2, "Should have been retried once");
+}
+
+#[tokio::test]
+async fn single_attempt_disables_retries() {
+    let inner = Arc::new(FlakyEventSink::failing(1, unavailable()));
+    let sink = RetrySink::new("flaky", inner.clone(), RetryParameters::default());
+
+    assert!(sink.sink(random_event()).await.is_err(), "Should fail");
+    assert_eq!(inner.calls(), 1, "Should not have been retried");
+}
@@ -0,0 +1,99 @@
+use crate::test_tools::*;
+use mqtt2influx_core::{Event, EventFilter, EventMatcher, EventSink, FieldValue, FilterSink, Metrics};
+use std::sync::Arc;
+
+fn living_room_sensor() -> Event {
+    Event::new("living_room_sensor")
+        .with_topic("zigbee2mqtt/living_room_sensor")
+        .with_tag("room", "living_room")
+        .with_field("temperature", FieldValue::Float(21.5))
+        .with_field("battery", FieldValue::Integer(90))
+}
+
+fn kitchen_plug() -> Event {
+    Event::new("kitchen_plug")
+        .with_topic("tasmota/kitchen_plug")
+        .with_tag("room", "kitchen")
+        .with_field("power", FieldValue::Float(12.0))
+}
+
+fn include(matcher: EventMatcher) -> EventFilter {
+    EventFilter {
+        include: Some(matcher),
+        exclude: None,
+    }
+}
+
+#[test]
+fn every_condition_must_hold() {
+    let matcher = EventMatcher {
+        devices: vec!["*_sensor".to_string(), "door".to_string()],
+        topics: vec!["zigbee2mqtt/*".to_string()],
+        tags: vec![("room".to_string(), "living_*".to_string())].into_iter().collect(),
+        fields: vec!["battery".to_string(), "voltage".to_string()],
+    };
+    assert!(matcher.matches(&living_room_sensor()), "Every condition holds");
+    assert!(!matcher.matches(&kitchen_plug()), "No condition holds");
+
+    let without_battery = Event {
+        fields: Default::default(),
+        ..living_room_sensor()
+    };
+    assert!(!matcher.matches(&without_battery), "Field condition does not hold");
+
+    let without_topic = Event {
+        topic: None,
+        ..living_room_sensor()
+    };
+    assert!(!matcher.matches(&without_topic), "Events without topic should not match topics");
+    assert!(
+        EventMatcher::default().matches(&without_topic),
+        "Empty matcher should match everything"
+    );
+}
+
+#[test]
+fn exclude_takes_precedence() {
+    let filter = EventFilter {
+        include: Some(EventMatcher {
+            fields: vec!["battery".to_string(), "power".to_string()],
+            ..Default::default()
+        }),
+        exclude: Some(EventMatcher {
+            devices: vec!["kitchen_*".to_string()],
+            ..Default::default()
+        }),
+    };
+    assert!(filter.accepts(&living_room_sensor()), "Included event should be accepted");
+    assert!(!filter.accepts(&kitchen_plug()), "Excluded event should be rejected");
+    assert!(
+        EventFilter::default().accepts(&kitchen_plug()),
+        "Empty filter should accept everything"
+    );
+}
+
+#[tokio::test]
+async fn skipped_events_succeed_and_are_counted() {
+    let inner = Arc::new(MockEventSink::default());
+    let filter = include(EventMatcher {
+        tags: vec![("room".to_string(), "living_room".to_string())].into_iter().collect(),
+        ..Default::default()
+    });
+    let metrics = Metrics::default();
+    let sink = FilterSink::new("dashboard", inner.clone(), filter).with_metrics(metrics.clone());
+
+    sink.sink(living_room_sensor()).await.expect("Accepted event should be stored");
+    sink.sink(kitchen_plug()).await.expect("Skipped event should not fail");
+    sink.sink(kitchen_plug()).await.expect("Skipped event should not fail");
+
+    let devices = inner.received().await.into_iter().map(|e| e.device_name).collect::<Vec<_>>();
+    assert_eq!(devices, vec!["living_room_sensor"], "Only accepted events should be stored");
+    assert_eq!(sink.skipped(), 2, "Skipped events should be counted");
+    assert_eq!(metrics.sink_filtered("dashboard"), 2, "Skipped events should be in the metrics");
+    assert!(
+        metrics
+            .render(&[])
+            .contains("mqtt2influx_sink_filtered_total{sink=\"dashboard\"} 2"),
+        "Skipped events should be rendered"
+    );
+}
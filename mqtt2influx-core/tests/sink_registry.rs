@@ -0,0 +1,125 @@
+use crate::test_tools::*;
+use mqtt2influx_core::serde_json::{json, Value};
+use mqtt2influx_core::{EventSink, QueueParameters, RetryParameters, SinkRegistry, SinkSetPolicy, SinkSpec};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+fn spec(name: &str, sink_type: &str) -> SinkSpec {
+    SinkSpec {
+        name: name.to_string(),
+        sink_type: sink_type.to_string(),
+        options: Value::Null,
+        retry: None,
+        queue: None,
+        filter: None,
+    }
+}
+
+/// Registry with a `mock` type storing its events in `sink` and its options in `options`.
+fn mock_registry(sink: Arc<MockEventSink>, options: Arc<Mutex<Vec<Value>>>) -> SinkRegistry {
+    let mut registry = SinkRegistry::default();
+    registry.register("mock", move |spec| {
+        let sink = sink.clone();
+        let options = options.clone();
+        async move {
+            options.lock().await.push(spec.options);
+            Ok(sink as Arc<dyn EventSink>)
+        }
+    });
+    registry
+}
+
+#[tokio::test]
+async fn sinks_are_built_from_their_specs() {
+    let sink = Arc::new(MockEventSink::default());
+    let options = Arc::new(Mutex::new(Vec::new()));
+    let registry = mock_registry(sink.clone(), options.clone());
+    assert_eq!(registry.types(), vec!["log", "mock"], "Types should match");
+
+    let mock = SinkSpec {
+        options: json!({"path": "/tmp/events"}),
+        ..spec("mock", "mock")
+    };
+    let graph = registry
+        .build(vec![mock, spec("log", "log")], SinkSetPolicy::All)
+        .await
+        .expect("Should build the sinks");
+    assert_eq!(graph.sinks.len(), 2, "Both sinks should be built");
+    assert!(graph.queues.is_empty(), "No queue should be created");
+    assert_eq!(
+        *options.lock().await,
+        vec![json!({"path": "/tmp/events"})],
+        "Options should be passed"
+    );
+
+    let event = random_event();
+    graph.sinks.sink(event.clone()).await.expect("Should be able to sink");
+    assert_eq!(sink.received().await, vec![event], "Event should be stored");
+}
+
+#[tokio::test]
+async fn queued_sinks_are_reported() {
+    let sink = Arc::new(MockEventSink::default());
+    let registry = mock_registry(sink.clone(), Arc::default());
+    let queued = SinkSpec {
+        queue: Some(QueueParameters::default()),
+        ..spec("queued", "mock")
+    };
+
+    let graph = registry
+        .build(vec![queued], SinkSetPolicy::All)
+        .await
+        .expect("Should build the sinks");
+    let names = graph.queues.iter().map(|q| q.name().to_string()).collect::<Vec<_>>();
+    assert_eq!(names, vec!["queued"], "Queue should be reported");
+
+    graph.sinks.sink(random_event()).await.expect("Should be able to sink");
+    wait_until("the queued event to be stored", || async { sink.received().await.len() == 1 }).await;
+}
+
+#[tokio::test]
+async fn invalid_specs_are_rejected() {
+    let registry = mock_registry(Arc::default(), Arc::default());
+
+    let res = registry.build(vec![spec("kafka", "kafka")], SinkSetPolicy::All).await;
+    assert!(res.is_err(), "Unknown types should be rejected");
+
+    let res = registry
+        .build(vec![spec("twice", "mock"), spec("twice", "log")], SinkSetPolicy::All)
+        .await;
+    assert!(res.is_err(), "Duplicated names should be rejected");
+
+    let res = registry.build(vec![], SinkSetPolicy::All).await;
+    assert!(res.is_err(), "At least one sink should be required");
+}
+
+#[tokio::test]
+async fn retrying_types_get_the_retry_parameters_instead_of_a_retry_sink() {
+    let flaky = Arc::new(FlakyEventSink::failing(1, unavailable()));
+    let retries = Arc::new(Mutex::new(Vec::new()));
+    let mut registry = SinkRegistry::default();
+    let (factory_sink, factory_retries) = (flaky.clone(), retries.clone());
+    registry.register_retrying("batching", move |spec| {
+        let sink = factory_sink.clone();
+        let retries = factory_retries.clone();
+        async move {
+            retries.lock().await.push(spec.retry.map(|retry| retry.max_attempts));
+            Ok(sink as Arc<dyn EventSink>)
+        }
+    });
+    let batching = SinkSpec {
+        retry: Some(RetryParameters {
+            max_attempts: 3,
+            ..Default::default()
+        }),
+        ..spec("batching", "batching")
+    };
+
+    let graph = registry
+        .build(vec![batching], SinkSetPolicy::All)
+        .await
+        .expect("Should build the sinks");
+    assert_eq!(*retries.lock().await, vec![Some(3)], "Factory should get the retry parameters");
+    assert!(graph.sinks.sink(random_event()).await.is_err(), "Error should not be retried");
+    assert_eq!(flaky.calls(), 1, "Sink should not be wrapped into a RetrySink");
+}
@@ -0,0 +1,78 @@
+use crate::test_tools::*;
+use mqtt2influx_core::{EventSink, QueueParameters, QueuedSink, SinkError, SinkSet, SinkSetError, SinkSetPolicy};
+use std::sync::Arc;
+
+#[tokio::test]
+async fn events_are_sent_to_every_member() {
+    let sinks = (0..3).map(|_| Arc::new(MockEventSink::default())).collect::<Vec<_>>();
+    let set = sinks.iter().enumerate().fold(SinkSet::new(SinkSetPolicy::All), |set, (i, sink)| {
+        set.with_sink(&format!("sink-{}", i), sink.clone())
+    });
+
+    let event = random_event();
+    set.sink(event.clone()).await.expect("Should be able to sink");
+    for sink in sinks.iter() {
+        assert_eq!(sink.received().await, vec![event.clone()], "Every member should receive the event");
+    }
+}
+
+#[tokio::test]
+async fn failed_members_are_reported() {
+    let healthy = Arc::new(MockEventSink::default());
+    let failing = Arc::new(FlakyEventSink::failing(1, unavailable()));
+    let set = SinkSet::new(SinkSetPolicy::All)
+        .with_sink("healthy", healthy.clone())
+        .with_sink("failing", failing.clone());
+
+    let e = set.sink(random_event()).await.expect_err("Should fail with a failed member");
+    let set_error = e.downcast_ref::<SinkSetError>().expect("Should be a SinkSetError");
+    assert_eq!(set_error.failed_sinks(), vec!["failing"], "Failed member should be reported");
+    assert_eq!(set_error.succeeded, 1, "One member should have succeeded");
+    assert_eq!(SinkError::find(&e), Some(&unavailable()), "Member error should be found");
+    assert_eq!(healthy.received().await.len(), 1, "Healthy member should still receive the event");
+}
+
+#[tokio::test]
+async fn best_effort_ignores_failures() {
+    let set = SinkSet::new(SinkSetPolicy::BestEffort)
+        .with_sink("first", Arc::new(FlakyEventSink::failing(1, unavailable())))
+        .with_sink("second", Arc::new(FlakyEventSink::failing(1, unavailable())));
+
+    set.sink(random_event()).await.expect("Failures should be ignored");
+}
+
+#[tokio::test]
+async fn quorum_requires_enough_members() {
+    let set = SinkSet::new(SinkSetPolicy::Quorum(2))
+        .with_sink("first", Arc::new(MockEventSink::default()))
+        .with_sink("second", Arc::new(MockEventSink::default()))
+        .with_sink("failing", Arc::new(FlakyEventSink::failing(2, unavailable())));
+    set.sink(random_event()).await.expect("Two members should be enough");
+
+    let set = SinkSet::new(SinkSetPolicy::Quorum(2))
+        .with_sink("healthy", Arc::new(MockEventSink::default()))
+        .with_sink("first", Arc::new(FlakyEventSink::failing(1, unavailable())))
+        .with_sink("second", Arc::new(FlakyEventSink::failing(1, unavailable())));
+    let e = set.sink(random_event()).await.expect_err("One member should not be enough");
+    let set_error = e.downcast_ref::<SinkSetError>().expect("Should be a SinkSetError");
+    assert_eq!(
+        set_error.failed_sinks(),
+        vec!["first", "second"],
+        "Failed members should be reported"
+    );
+}
+
+#[tokio::test]
+async fn close_stores_the_events_queued_by_members() {
+    let inner = Arc::new(MockEventSink::default());
+    let set = SinkSet::new(SinkSetPolicy::BestEffort).with_sink(
+        "queued",
+        Arc::new(QueuedSink::new("queued", inner.clone(), QueueParameters::default())),
+    );
+
+    for _ in 0..10 {
+        set.sink(random_event()).await.expect("Should be able to sink");
+    }
+    set.close().await.expect("Should be able to close");
+    assert_eq!(inner.received().await.len(), 10, "Queued events should be stored once closed");
+}
@@ -0,0 +1,86 @@
+use mqtt2influx_core::topic::{glob_match, match_topic, validate_filter};
+use mqtt2influx_core::Subscription;
+
+fn subscription(topic: &str, device_name: &str) -> Subscription {
+    Subscription {
+        topic: topic.to_string(),
+        device_name: device_name.to_string(),
+        qos: 0,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn exact_topics_match() {
+    assert_eq!(match_topic("a/b/c", "a/b/c"), Some(vec![]));
+    assert_eq!(match_topic("a/b/c", "a/b"), None);
+    assert_eq!(match_topic("a/b", "a/b/c"), None);
+}
+
+#[test]
+fn single_level_wildcard_captures_one_level() {
+    assert_eq!(match_topic("zigbee2mqtt/+", "zigbee2mqtt/kitchen"), Some(vec!["kitchen"]));
+    assert_eq!(match_topic("+/sensors/+", "home/sensors/room"), Some(vec!["home", "room"]));
+    assert_eq!(match_topic("zigbee2mqtt/+", "zigbee2mqtt/kitchen/state"), None);
+    assert_eq!(match_topic("zigbee2mqtt/+", "zigbee2mqtt"), None);
+}
+
+#[test]
+fn multi_level_wildcard_captures_the_rest() {
+    assert_eq!(match_topic("home/#", "home/floor1/kitchen"), Some(vec!["floor1/kitchen"]));
+    assert_eq!(match_topic("home/#", "home"), Some(vec![""]));
+    assert_eq!(match_topic("#", "home/kitchen"), Some(vec!["home/kitchen"]));
+    assert_eq!(match_topic("home/#", "office/kitchen"), None);
+}
+
+#[test]
+fn wildcards_do_not_match_system_topics() {
+    assert_eq!(match_topic("#", "$SYS/broker"), None);
+    assert_eq!(match_topic("+/broker", "$SYS/broker"), None);
+    assert_eq!(match_topic("$SYS/#", "$SYS/broker"), Some(vec!["broker"]));
+}
+
+#[test]
+fn invalid_filters_are_rejected() {
+    assert!(validate_filter("").is_err());
+    assert!(validate_filter("a/#/b").is_err());
+    assert!(validate_filter("a/b+").is_err());
+    assert!(validate_filter("a/#b").is_err());
+    assert_eq!(validate_filter("a/+/#").unwrap(), 2);
+}
+
+#[test]
+fn device_name_uses_captured_segments() {
+    let s = subscription("zigbee2mqtt/+/+", "{2} ({1})");
+    assert_eq!(s.device_name_for("zigbee2mqtt/kitchen/plug"), Some("plug (kitchen)".to_string()));
+    assert_eq!(s.device_name_for("other/kitchen/plug"), None);
+
+    let s = subscription("some/topic/room", "Room");
+    assert_eq!(s.device_name_for("some/topic/room"), Some("Room".to_string()));
+}
+
+#[test]
+fn subscription_validation() {
+    assert!(subscription("zigbee2mqtt/+", "{1}").validate().is_ok());
+    assert!(subscription("zigbee2mqtt/+", "{2}").validate().is_err());
+    assert!(subscription("zigbee2mqtt/+", "{0}").validate().is_err());
+    assert!(subscription("zigbee2mqtt/+", "{1").validate().is_err());
+    assert!(subscription("zigbee2mqtt/#/x", "Room").validate().is_err());
+
+    let mut s = subscription("zigbee2mqtt/+", "{1}");
+    s.qos = 3;
+    assert!(s.validate().is_err());
+}
+
+#[test]
+fn globs_match_any_characters() {
+    assert!(glob_match("zigbee2mqtt/*", "zigbee2mqtt/kitchen"));
+    assert!(glob_match("zigbee2mqtt/*", "zigbee2mqtt/living/room"), "* should cross levels");
+    assert!(glob_match("*_sensor", "living_room_sensor"));
+    assert!(glob_match("room?", "room1"));
+    assert!(glob_match("*", ""));
+    assert!(glob_match("a*b*c", "aXbYbZc"), "Should backtrack");
+    assert!(!glob_match("room?", "room"));
+    assert!(!glob_match("zigbee2mqtt/*", "tasmota/kitchen"));
+    assert!(!glob_match("kitchen", "kitchen2"));
+}
@@ -0,0 +1,211 @@
+use actix_service::{Service, Transform};
+use actix_web::body::EitherBody;
+use actix_web::http::header;
+use actix_web::{dev::ServiceRequest, dev::ServiceResponse, web, Error, HttpResponse};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use futures::future::{ok, Ready};
+use futures::Future;
+use sha1::{Digest, Sha1};
+use std::pin::Pin;
+use std::rc::Rc;
+
+const SHA_PREFIX: &str = "{SHA}";
+const BCRYPT_PREFIXES: [&str; 3] = ["$2y$", "$2b$", "$2a$"];
+const ACCESS_TOKEN_PARAM: &str = "access_token";
+
+/// Password hash of an htpasswd user.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PasswordHash {
+    /// `htpasswd -B`
+    Bcrypt(String),
+    /// `htpasswd -s`, unsalted SHA-1 digest
+    Sha1(Vec<u8>),
+}
+
+/// Parses an htpasswd line with a bcrypt or `{SHA}` hash into the user and its password hash.
+pub fn parse_user(line: &str) -> Result<(String, PasswordHash), String> {
+    let (user, hash) = line.split_once(':').ok_or_else(|| "expected user:hash".to_string())?;
+    if user.is_empty() {
+        return Err("empty user".to_string());
+    }
+    if BCRYPT_PREFIXES.iter().any(|prefix| hash.starts_with(prefix)) {
+        // Checks the cost and the salt, so a malformed hash is reported now instead of rejecting every request
+        bcrypt::verify("", hash).map_err(|e| format!("invalid hash for [{}]: {}", user, e))?;
+        return Ok((user.to_string(), PasswordHash::Bcrypt(hash.to_string())));
+    }
+    let digest = hash
+        .strip_prefix(SHA_PREFIX)
+        .ok_or_else(|| format!("unsupported hash for [{}], only bcrypt and {{SHA}} are supported", user))?;
+    let digest = STANDARD.decode(digest).map_err(|e| format!("invalid hash for [{}]: {}", user, e))?;
+    if digest.len() != Sha1::output_size() {
+        return Err(format!("invalid hash for [{}]: not a SHA-1 digest", user));
+    }
+    Ok((user.to_string(), PasswordHash::Sha1(digest)))
+}
+
+/// Compares in a time that does not depend on where the values differ.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+enum Check {
+    Allowed,
+    Denied,
+    /// bcrypt is slow on purpose, so it is checked on the blocking thread pool
+    Bcrypt {
+        password: String,
+        hash: String,
+    },
+}
+
+struct Credentials {
+    tokens: Vec<String>,
+    users: Vec<(String, PasswordHash)>,
+    /// Paths served without credentials
+    open_paths: Vec<String>,
+}
+
+impl Credentials {
+    fn token_valid(&self, token: &str) -> bool {
+        self.tokens.iter().any(|t| constant_time_eq(t.as_bytes(), token.as_bytes()))
+    }
+
+    fn check_basic(&self, encoded: &str) -> Check {
+        let decoded = match STANDARD.decode(encoded).map(String::from_utf8) {
+            Ok(Ok(decoded)) => decoded,
+            _ => return Check::Denied,
+        };
+        let (user, password) = match decoded.split_once(':') {
+            Some(credentials) => credentials,
+            None => return Check::Denied,
+        };
+        match self.users.iter().find(|(u, _)| u == user) {
+            Some((_, PasswordHash::Sha1(digest))) if constant_time_eq(digest, Sha1::digest(password.as_bytes()).as_slice()) => {
+                Check::Allowed
+            }
+            Some((_, PasswordHash::Bcrypt(hash))) => Check::Bcrypt {
+                password: password.to_string(),
+                hash: hash.clone(),
+            },
+            _ => Check::Denied,
+        }
+    }
+
+    /// Accepts `Authorization: Bearer`, `Authorization: Basic`, and the `access_token` query parameter for
+    /// clients that cannot set headers, such as `EventSource` and browser WebSockets.
+    fn check(&self, req: &ServiceRequest) -> Check {
+        if self.open_paths.iter().any(|p| p == req.path()) {
+            return Check::Allowed;
+        }
+        let authorization = req.headers().get(header::AUTHORIZATION).and_then(|h| h.to_str().ok());
+        if let Some(authorization) = authorization {
+            if let Some(token) = authorization.strip_prefix("Bearer ") {
+                return if self.token_valid(token.trim()) {
+                    Check::Allowed
+                } else {
+                    Check::Denied
+                };
+            }
+            if let Some(encoded) = authorization.strip_prefix("Basic ") {
+                return self.check_basic(encoded.trim());
+            }
+            return Check::Denied;
+        }
+        let params = web::Query::<Vec<(String, String)>>::from_query(req.query_string()).map(web::Query::into_inner);
+        let allowed = params
+            .unwrap_or_default()
+            .iter()
+            .any(|(name, value)| name == ACCESS_TOKEN_PARAM && self.token_valid(value));
+        if allowed {
+            Check::Allowed
+        } else {
+            Check::Denied
+        }
+    }
+
+    fn unauthorized(&self, req: ServiceRequest) -> ServiceResponse {
+        debug!("Unauthorized request [path={}]", req.path());
+        let challenge = if self.users.is_empty() {
+            "Bearer"
+        } else {
+            "Basic realm=\"mqtt2influx\""
+        };
+        let res = HttpResponse::Unauthorized()
+            .append_header((header::WWW_AUTHENTICATE, challenge))
+            .finish();
+        req.into_response(res)
+    }
+}
+
+/// Rejects the requests without a valid token or user. Every request passes when neither is configured.
+pub struct Auth {
+    credentials: Rc<Credentials>,
+}
+
+impl Auth {
+    pub fn new(tokens: Vec<String>, users: Vec<(String, PasswordHash)>, open_paths: Vec<String>) -> Self {
+        Self {
+            credentials: Rc::new(Credentials { tokens, users, open_paths }),
+        }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for Auth
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = AuthMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(AuthMiddleware {
+            service: Rc::new(service),
+            credentials: self.credentials.clone(),
+        })
+    }
+}
+
+pub struct AuthMiddleware<S> {
+    service: Rc<S>,
+    credentials: Rc<Credentials>,
+}
+
+#[allow(clippy::type_complexity)]
+impl<S, B> Service<ServiceRequest> for AuthMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    actix_service::forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let credentials = self.credentials.clone();
+        let enabled = !credentials.tokens.is_empty() || !credentials.users.is_empty();
+        let check = if enabled { credentials.check(&req) } else { Check::Allowed };
+        let service = self.service.clone();
+        Box::pin(async move {
+            let allowed = match check {
+                Check::Allowed => true,
+                Check::Denied => false,
+                Check::Bcrypt { password, hash } => web::block(move || bcrypt::verify(password, &hash).unwrap_or(false))
+                    .await
+                    .unwrap_or(false),
+            };
+            if !allowed {
+                return Ok(credentials.unauthorized(req).map_into_right_body());
+            }
+            Ok(service.call(req).await?.map_into_left_body())
+        })
+    }
+}
@@ -0,0 +1,106 @@
+use actix_service::{Service, Transform};
+use actix_web::body::EitherBody;
+use actix_web::http::header::{self, HeaderValue};
+use actix_web::http::Method;
+use actix_web::{dev::ServiceRequest, dev::ServiceResponse, Error, HttpResponse};
+use futures::future::{ok, Ready};
+use futures::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+
+const ANY_ORIGIN: &str = "*";
+
+/// Lets browsers on `origins` call the API, `*` allowing any origin. Answers the preflight requests itself,
+/// so they never reach the routes or the authentication.
+pub struct Cors {
+    origins: Rc<Vec<String>>,
+}
+
+impl Cors {
+    pub fn new(origins: Vec<String>) -> Self {
+        Self { origins: Rc::new(origins) }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for Cors
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = CorsMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(CorsMiddleware {
+            service,
+            origins: self.origins.clone(),
+        })
+    }
+}
+
+pub struct CorsMiddleware<S> {
+    service: S,
+    origins: Rc<Vec<String>>,
+}
+
+impl<S> CorsMiddleware<S> {
+    /// Value of `Access-Control-Allow-Origin` for a request from `origin`, `None` when it is not allowed.
+    fn allowed_origin(&self, origin: Option<&HeaderValue>) -> Option<HeaderValue> {
+        if self.origins.iter().any(|o| o == ANY_ORIGIN) {
+            return Some(HeaderValue::from_static(ANY_ORIGIN));
+        }
+        let origin = origin?;
+        let allowed = self.origins.iter().any(|o| origin.to_str().is_ok_and(|origin| origin == o));
+        allowed.then(|| origin.clone())
+    }
+}
+
+#[allow(clippy::type_complexity)]
+impl<S, B> Service<ServiceRequest> for CorsMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    actix_service::forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let allowed_origin = self.allowed_origin(req.headers().get(header::ORIGIN));
+        // The response depends on the request origin, unless any origin is allowed
+        let vary = !self.origins.iter().any(|o| o == ANY_ORIGIN);
+
+        if req.method() == Method::OPTIONS && req.headers().contains_key(header::ACCESS_CONTROL_REQUEST_METHOD) {
+            let mut res = HttpResponse::NoContent();
+            if let Some(origin) = allowed_origin {
+                res.append_header((header::ACCESS_CONTROL_ALLOW_ORIGIN, origin))
+                    .append_header((header::ACCESS_CONTROL_ALLOW_METHODS, "GET"))
+                    .append_header((header::ACCESS_CONTROL_ALLOW_HEADERS, "Authorization"));
+            }
+            if vary {
+                res.append_header((header::VARY, "Origin"));
+            }
+            let res = res.finish();
+            return Box::pin(async move { Ok(req.into_response(res).map_into_right_body()) });
+        }
+
+        let fut = self.service.call(req);
+        Box::pin(async move {
+            let mut res = fut.await?;
+            if let Some(origin) = allowed_origin {
+                res.headers_mut().insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, origin);
+            }
+            if vary {
+                res.headers_mut().append(header::VARY, HeaderValue::from_static("Origin"));
+            }
+            Ok(res.map_into_left_body())
+        })
+    }
+}
@@ -1,11 +1,20 @@
+use crate::conf::ApiConfig;
+use actix_web::dev::Server;
 use actix_web::{web, App, HttpResponse, HttpServer};
 use mqtt2influx_core::anyhow::Result;
+use mqtt2influx_core::{FieldValue, Metrics, QueueStats, QueuedSink};
 use std::sync::Arc;
 
+mod auth_middleware;
+mod cors_middleware;
 mod request_id_middleware;
 mod request_logger_middleware;
+mod stream;
 mod types;
 
+pub use auth_middleware::{parse_user, Auth, PasswordHash};
+pub use cors_middleware::Cors;
+pub use request_id_middleware::{RequestId, RequestIdType};
 pub use types::*;
 
 #[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
@@ -15,29 +24,137 @@ struct ApiValuesResponse {
 
 async fn get(state: web::Data<Arc<ApiState>>) -> HttpResponse {
     let values = state.values().await;
+    HttpResponse::Ok().json(&ApiValuesResponse { values })
+}
+
+#[derive(Clone, Debug, serde::Deserialize)]
+struct DevicesQuery {
+    /// Only devices reporting a `battery` field lower than this
+    battery_below: Option<f64>,
+}
+
+/// Latest reading of every device, sorted by name.
+async fn devices(state: web::Data<Arc<ApiState>>, query: web::Query<DevicesQuery>) -> HttpResponse {
+    let mut values = state.values().await;
+    if let Some(battery_below) = query.battery_below {
+        values.retain(|value| {
+            value
+                .field("battery")
+                .and_then(FieldValue::as_f64)
+                .is_some_and(|battery| battery < battery_below)
+        });
+    }
+    values.sort_by(|a, b| a.name().cmp(b.name()));
+    HttpResponse::Ok().json(&ApiValuesResponse { values })
+}
+
+#[derive(Clone, Debug, serde::Deserialize)]
+struct HistoryQuery {
+    /// Milliseconds since the epoch
+    since: Option<i64>,
+    limit: Option<usize>,
+}
+
+#[derive(Clone, Debug, serde::Serialize)]
+struct ApiHistoryResponse {
+    name: String,
+    values: Vec<ApiEvent>,
+}
+
+fn device_not_found(name: &str) -> HttpResponse {
+    HttpResponse::NotFound().body(format!("Device [{}] not found", name))
+}
+
+async fn device(state: web::Data<Arc<ApiState>>, name: web::Path<String>) -> HttpResponse {
+    match state.latest(&name).await {
+        Some(value) => HttpResponse::Ok().json(&value),
+        None => device_not_found(&name),
+    }
+}
+
+async fn device_history(state: web::Data<Arc<ApiState>>, name: web::Path<String>, query: web::Query<HistoryQuery>) -> HttpResponse {
+    match state.history(&name, query.since, query.limit).await {
+        Some(values) => HttpResponse::Ok().json(&ApiHistoryResponse {
+            name: name.into_inner(),
+            values,
+        }),
+        None => device_not_found(&name),
+    }
+}
+
+#[derive(Clone, Debug, serde::Serialize)]
+struct ApiQueuesResponse {
+    queues: Vec<QueueStats>,
+}
+
+async fn queues(queues: web::Data<Vec<Arc<QueuedSink>>>) -> HttpResponse {
+    let queues = queues.iter().map(|queue| queue.stats()).collect();
+    HttpResponse::Ok().json(&ApiQueuesResponse { queues })
+}
+
+async fn metrics(metrics: web::Data<Metrics>, queues: web::Data<Vec<Arc<QueuedSink>>>) -> HttpResponse {
+    let queues = queues.iter().map(|queue| queue.stats()).collect::<Vec<_>>();
     HttpResponse::Ok()
-        .append_header(("Access-Control-Allow-Origin", "*"))
-        .json(&ApiValuesResponse { values })
+        .content_type("text/plain; version=0.0.4")
+        .body(metrics.render(&queues))
 }
 
 async fn health() -> HttpResponse {
     HttpResponse::Ok().body("Running")
 }
 
-pub async fn run(port: u16, state: Arc<ApiState>) -> Result<()> {
+/// Every route of the API. They expect an `ApiConfig`, an `Arc<ApiState>`, the `Vec<Arc<QueuedSink>>` to monitor and
+/// the `Metrics` as app data.
+pub fn routes(config: &mut web::ServiceConfig) {
+    config
+        .route("/", web::get().to(get))
+        .route("/stream", web::get().to(stream::sse))
+        .route("/ws", web::get().to(stream::ws))
+        .route("/devices", web::get().to(devices))
+        .route("/devices/{name}", web::get().to(device))
+        .route("/devices/{name}/history", web::get().to(device_history))
+        .route("/queues", web::get().to(self::queues))
+        .route("/metrics", web::get().to(self::metrics))
+        .route("/health", web::get().to(health));
+}
+
+/// Binds the API server. Signals are left to the caller, which stops the server through its handle.
+pub fn server(port: u16, config: ApiConfig, state: Arc<ApiState>, queues: Vec<Arc<QueuedSink>>, metrics: Metrics) -> Result<Server> {
     let addr = format!("0.0.0.0:{}", port);
+    let users = config.auth.users()?;
+    if !config.auth.tokens.is_empty() || !users.is_empty() {
+        info!(
+            "API requires authentication [tokens={}] [users={}]",
+            config.auth.tokens.len(),
+            users.len()
+        );
+    }
+    for (user, hash) in &users {
+        if let PasswordHash::Sha1(_) = hash {
+            warn!("API user has an unsalted SHA-1 password hash, prefer bcrypt [user={}]", user);
+        }
+    }
     info!("Started API [http://{}]", &addr);
-    HttpServer::new(move || {
-        let ignored = vec!["/health".to_string()];
+    let server = HttpServer::new(move || {
+        let ignored = vec!["/health".to_string(), "/metrics".to_string()];
+        let open = if config.auth.open_health {
+            vec!["/health".to_string()]
+        } else {
+            vec![]
+        };
         App::new()
+            .wrap(Auth::new(config.auth.tokens.clone(), users.clone(), open))
             .wrap(request_logger_middleware::RequestLogger::new_with_ignored_paths(ignored))
-            .wrap(request_id_middleware::RequestId)
+            .wrap(RequestId)
+            .wrap(Cors::new(config.cors_origins.clone()))
+            .app_data(web::Data::new(config.clone()))
             .app_data(web::Data::new(state.clone()))
-            .route("/", web::get().to(get))
-            .route("/health", web::get().to(health))
+            .app_data(web::Data::new(queues.clone()))
+            .app_data(web::Data::new(metrics.clone()))
+            .configure(routes)
     })
+    .disable_signals()
     .bind(addr)?
-    .run()
-    .await?;
-    Ok(())
+    .run();
+    Ok(server)
 }
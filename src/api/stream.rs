@@ -0,0 +1,88 @@
+use super::ApiState;
+use crate::conf::ApiConfig;
+use actix_web::{web, HttpRequest, HttpResponse};
+use actix_ws::{CloseCode, CloseReason, Message};
+use mqtt2influx_core::serde_json;
+use std::sync::Arc;
+use tokio::time::{interval_at, Instant, Interval};
+
+#[derive(Clone, Debug, serde::Deserialize)]
+pub struct StreamQuery {
+    /// Only streams the events of this device when set
+    device: Option<String>,
+}
+
+fn keepalive(config: &ApiConfig) -> Interval {
+    let period = config.stream_keepalive();
+    interval_at(Instant::now() + period, period)
+}
+
+/// Server-Sent Events, one `data:` line with the JSON event per event, and a comment line as keepalive.
+pub async fn sse(state: web::Data<Arc<ApiState>>, config: web::Data<ApiConfig>, query: web::Query<StreamQuery>) -> HttpResponse {
+    let rx = state.subscribe(query.into_inner().device);
+    let events = futures::stream::unfold((rx, keepalive(&config)), |(mut rx, mut keepalive)| async move {
+        let chunk = tokio::select! {
+            // `None` once the client has been dropped for being too slow or on shutdown, which ends the response
+            event = rx.recv() => format!("data: {}\n\n", serde_json::to_string(&event?).ok()?),
+            _ = keepalive.tick() => ": keepalive\n\n".to_string(),
+        };
+        Some((Ok::<_, actix_web::Error>(web::Bytes::from(chunk)), (rx, keepalive)))
+    });
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .append_header(("Cache-Control", "no-cache"))
+        .streaming(events)
+}
+
+/// WebSocket sending every event as a JSON text message, and pings as keepalive.
+pub async fn ws(
+    req: HttpRequest,
+    body: web::Payload,
+    state: web::Data<Arc<ApiState>>,
+    config: web::Data<ApiConfig>,
+    query: web::Query<StreamQuery>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let (response, mut session, mut messages) = actix_ws::handle(&req, body)?;
+    let mut rx = state.subscribe(query.into_inner().device);
+    let mut keepalive = keepalive(&config);
+    actix_web::rt::spawn(async move {
+        let reason = loop {
+            tokio::select! {
+                event = rx.recv() => match event.map(|event| serde_json::to_string(&event)) {
+                    Some(Ok(json)) => {
+                        if session.text(json).await.is_err() {
+                            return;
+                        }
+                    }
+                    Some(Err(e)) => {
+                        error!("Error serializing streamed event: {}", e);
+                        break Some(CloseReason::from(CloseCode::Error));
+                    }
+                    None => {
+                        break Some(CloseReason {
+                            code: CloseCode::Again,
+                            description: Some("Client too slow or server stopping".to_string()),
+                        })
+                    }
+                },
+                _ = keepalive.tick() => {
+                    if session.ping(b"").await.is_err() {
+                        return;
+                    }
+                }
+                message = messages.recv() => match message {
+                    Some(Ok(Message::Ping(bytes))) => {
+                        if session.pong(&bytes).await.is_err() {
+                            return;
+                        }
+                    }
+                    Some(Ok(Message::Close(reason))) => break reason,
+                    Some(Ok(_)) => {}
+                    Some(Err(_)) | None => break None,
+                },
+            }
+        };
+        let _ = session.close(reason).await;
+    });
+    Ok(response)
+}
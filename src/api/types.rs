@@ -1,50 +1,192 @@
 use mqtt2influx_core::anyhow::Result;
-use mqtt2influx_core::chrono::Utc;
-use mqtt2influx_core::{async_trait, Event, EventSink};
-use std::collections::HashMap;
+use mqtt2influx_core::{async_trait, DeviceStatus, DeviceStatuses, Event, EventSink, FieldValue, STATUS_MEASUREMENT};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::Mutex;
+use tokio::sync::mpsc::{channel, error::TrySendError, Receiver, Sender};
 use tokio::sync::RwLock;
 
+/// Keys of `ApiEvent` that event fields cannot take, as fields are serialized next to them.
+const RESERVED_KEYS: [&str; 3] = ["name", "updated_at", "status"];
+
 #[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
 pub struct ApiEvent {
     name: String,
-    temperature: f32,
-    humidity: f32,
+    /// Every field of the event, such as `temperature`, `battery` or `linkquality`
+    #[serde(flatten)]
+    fields: BTreeMap<String, FieldValue>,
     updated_at: i64,
+    /// Only set for the latest reading of the devices with a `max_silence_ms`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    status: Option<DeviceStatus>,
+}
+
+impl ApiEvent {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn field(&self, name: &str) -> Option<&FieldValue> {
+        self.fields.get(name)
+    }
+}
+
+/// Latest reading of a device, plus the most recent ones in the order they were received.
+struct DeviceState {
+    latest: ApiEvent,
+    history: VecDeque<ApiEvent>,
+}
+
+/// Client of `/stream` or `/ws`, only interested in `device` when set.
+struct Subscriber {
+    device: Option<String>,
+    tx: Sender<ApiEvent>,
 }
 
 pub struct ApiState {
-    contents: RwLock<HashMap<String, ApiEvent>>,
+    contents: RwLock<HashMap<String, DeviceState>>,
+    history_size: usize,
+    subscribers: Mutex<Vec<Subscriber>>,
+    stream_buffer: usize,
+    statuses: DeviceStatuses,
 }
 
 impl ApiState {
+    /// Keeps the last `history_size` readings of every device, and up to `stream_buffer` events per streaming client.
+    pub fn new(history_size: usize, stream_buffer: usize) -> Self {
+        Self {
+            contents: RwLock::new(HashMap::new()),
+            history_size,
+            subscribers: Mutex::new(Vec::new()),
+            stream_buffer,
+            statuses: DeviceStatuses::default(),
+        }
+    }
+
+    /// Reports the status of the devices from `statuses`.
+    pub fn with_statuses(mut self, statuses: DeviceStatuses) -> Self {
+        self.statuses = statuses;
+        self
+    }
+
+    fn with_status(&self, mut event: ApiEvent) -> ApiEvent {
+        event.status = self.statuses.get(&event.name);
+        event
+    }
+
+    /// Receives every new event, or only those of `device`. The channel is closed if the client falls
+    /// `stream_buffer` events behind, so a slow client never holds back the sink.
+    pub fn subscribe(&self, device: Option<String>) -> Receiver<ApiEvent> {
+        let (tx, rx) = channel(self.stream_buffer);
+        self.subscribers
+            .lock()
+            .expect("Subscribers lock poisoned")
+            .push(Subscriber { device, tx });
+        rx
+    }
+
+    /// Streaming clients still subscribed. Clients that went away are removed on the next event.
+    pub fn subscribers(&self) -> usize {
+        self.subscribers.lock().expect("Subscribers lock poisoned").len()
+    }
+
+    fn publish(&self, event: &ApiEvent) {
+        let mut subscribers = self.subscribers.lock().expect("Subscribers lock poisoned");
+        subscribers.retain(|subscriber| {
+            if subscriber.device.as_ref().is_some_and(|device| device != &event.name) {
+                return !subscriber.tx.is_closed();
+            }
+            match subscriber.tx.try_send(event.clone()) {
+                Ok(_) => true,
+                Err(TrySendError::Full(_)) => {
+                    warn!("Dropping streaming client, it is {} events behind", self.stream_buffer);
+                    false
+                }
+                Err(TrySendError::Closed(_)) => false,
+            }
+        });
+    }
+
     pub async fn values(&self) -> Vec<ApiEvent> {
-        self.contents.read().await.values().cloned().collect()
+        let contents = self.contents.read().await;
+        contents.values().map(|device| self.with_status(device.latest.clone())).collect()
     }
-}
 
-impl Default for ApiState {
-    fn default() -> Self {
-        Self {
-            contents: RwLock::new(HashMap::new()),
+    pub async fn latest(&self, name: &str) -> Option<ApiEvent> {
+        self.contents
+            .read()
+            .await
+            .get(name)
+            .map(|device| self.with_status(device.latest.clone()))
+    }
+
+    /// The last `limit` readings of the device updated at `since` or later, oldest first.
+    /// `None` when nothing was received from the device.
+    pub async fn history(&self, name: &str, since: Option<i64>, limit: Option<usize>) -> Option<Vec<ApiEvent>> {
+        let contents = self.contents.read().await;
+        let device = contents.get(name)?;
+        let mut history = device
+            .history
+            .iter()
+            .filter(|event| since.is_none_or(|since| event.updated_at >= since))
+            .cloned()
+            .collect::<Vec<_>>();
+        if let Some(limit) = limit {
+            history.drain(..history.len().saturating_sub(limit));
         }
+        Some(history)
     }
 }
 
 #[async_trait::async_trait]
 impl EventSink for ApiState {
     async fn sink(&self, event: Event) -> Result<()> {
-        debug!(
-            "Updating API state [name={}] [temp={}] [humid={}]",
-            event.device_name, event.temperature, event.humidity
-        );
+        // Status changes carry no reading, they are only streamed with the latest one
+        if event.measurement.as_deref() == Some(STATUS_MEASUREMENT) {
+            if let Some(device) = self.contents.read().await.get(&event.device_name) {
+                self.publish(&self.with_status(device.latest.clone()));
+            }
+            return Ok(());
+        }
+        debug!("Updating API state [name={}] [fields={:?}]", event.device_name, event.fields);
+        let updated_at = event.time().timestamp_millis();
+        let device_name = event.device_name;
+        let mut fields = event.fields;
+        fields.retain(|name, _| {
+            let reserved = RESERVED_KEYS.contains(&name.as_str());
+            if reserved {
+                debug!(
+                    "Field [{}] of [{}] is not served by the API, the key is reserved",
+                    name, device_name
+                );
+            }
+            !reserved
+        });
         let mut contents = self.contents.write().await;
         let api_event = ApiEvent {
-            name: event.device_name.clone(),
-            temperature: event.temperature,
-            humidity: event.humidity,
-            updated_at: Utc::now().naive_utc().timestamp_millis(),
+            name: device_name.clone(),
+            fields,
+            updated_at,
+            status: None,
         };
-        contents.insert(event.device_name, api_event);
+        let history_size = self.history_size;
+        let device = contents.entry(device_name).or_insert_with(|| DeviceState {
+            latest: api_event.clone(),
+            history: VecDeque::with_capacity(history_size),
+        });
+        if history_size > 0 {
+            if device.history.len() == history_size {
+                device.history.pop_front();
+            }
+            device.history.push_back(api_event.clone());
+        }
+        self.publish(&self.with_status(api_event.clone()));
+        device.latest = api_event;
+        Ok(())
+    }
+
+    /// Ends the streams, so their connections do not hold back the API server shutdown.
+    async fn close(&self) -> Result<()> {
+        self.subscribers.lock().expect("Subscribers lock poisoned").clear();
         Ok(())
     }
 }
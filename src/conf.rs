@@ -1,6 +1,16 @@
+use crate::api::{parse_user, PasswordHash};
 use config::{Config as CConfig, ConfigError, Environment, File};
-use mqtt2influx_core::{InfluxDbConnectionParameters, InfluxDbCredentials, Subscription};
+use mqtt2influx_core::serde_json::{self, Map, Value};
+use mqtt2influx_core::sink::influx::{validate_retention_policy, READINGS_TABLE};
+use mqtt2influx_core::utils::Backoff;
+use mqtt2influx_core::{
+    default_retry_classifier, EventFilter, InfluxDbApi, InfluxDbBatchParameters, InfluxDbBufferParameters, InfluxDbConnectionParameters,
+    InfluxDbCredentials, MqttClientAuth, MqttConnectionParameters, MqttCredentials, MqttReconnectParameters, MqttTlsParameters,
+    OverflowPolicy, QueueParameters, RetryClassifier, RetryParameters, SinkError, SinkSetPolicy, SinkSpec, Subscription,
+};
 use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
 
 const DEFAULT_FILE_NAME: &str = "mqtt2influx.toml";
 const DEFAULT_PORT: u16 = 3333;
@@ -23,19 +33,431 @@ fn default_client_id() -> String {
     "mqtt2influx-client".to_string()
 }
 
+fn default_influx_version() -> u8 {
+    1
+}
+
+fn default_influx_measurement() -> String {
+    READINGS_TABLE.to_string()
+}
+
+fn default_influx_batch_size() -> usize {
+    1
+}
+
+fn default_influx_flush_interval_ms() -> u64 {
+    1000
+}
+
+fn default_influx_buffer_max_mb() -> u64 {
+    100
+}
+
+fn default_influx_buffer_replay_interval_ms() -> u64 {
+    5000
+}
+
+fn default_retry_max_attempts() -> u32 {
+    3
+}
+
+fn default_retry_initial_delay_ms() -> u64 {
+    200
+}
+
+fn default_retry_max_delay_ms() -> u64 {
+    10_000
+}
+
+fn default_retry_multiplier() -> f64 {
+    2.0
+}
+
+fn default_retry_jitter() -> f64 {
+    0.2
+}
+
+fn default_queue_capacity() -> usize {
+    1000
+}
+
+fn default_shutdown_timeout_ms() -> u64 {
+    10_000
+}
+
+fn default_api_history_size() -> usize {
+    100
+}
+
+fn default_api_stream_buffer() -> usize {
+    64
+}
+
+fn default_api_stream_keepalive_ms() -> u64 {
+    15_000
+}
+
+fn default_api_cors_origins() -> Vec<String> {
+    vec!["*".to_string()]
+}
+
+fn default_keep_alive() -> u16 {
+    60
+}
+
+fn default_clean_session() -> bool {
+    true
+}
+
+fn default_reconnect_initial_delay_ms() -> u64 {
+    1000
+}
+
+fn default_reconnect_max_delay_ms() -> u64 {
+    60_000
+}
+
+fn default_reconnect_multiplier() -> f64 {
+    2.0
+}
+
+fn default_reconnect_jitter() -> f64 {
+    0.2
+}
+
 #[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
 pub struct Connection {
     pub host: String,
     pub port: u16,
+    #[serde(default = "default_keep_alive")]
+    pub keep_alive: u16,
+    #[serde(default = "default_clean_session")]
+    pub clean_session: bool,
     pub username: Option<String>,
     pub password: Option<String>,
+    pub tls: Option<TlsConnection>,
+    #[serde(default)]
+    pub reconnect: ReconnectConnection,
+}
+
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub struct ReconnectConnection {
+    #[serde(default = "default_reconnect_initial_delay_ms")]
+    pub initial_delay_ms: u64,
+    #[serde(default = "default_reconnect_max_delay_ms")]
+    pub max_delay_ms: u64,
+    #[serde(default = "default_reconnect_multiplier")]
+    pub multiplier: f64,
+    #[serde(default = "default_reconnect_jitter")]
+    pub jitter: f64,
+    pub max_retries: Option<u32>,
+}
+
+impl Default for ReconnectConnection {
+    fn default() -> Self {
+        Self {
+            initial_delay_ms: default_reconnect_initial_delay_ms(),
+            max_delay_ms: default_reconnect_max_delay_ms(),
+            multiplier: default_reconnect_multiplier(),
+            jitter: default_reconnect_jitter(),
+            max_retries: None,
+        }
+    }
+}
+
+impl ReconnectConnection {
+    pub fn as_reconnect_parameters(&self) -> MqttReconnectParameters {
+        MqttReconnectParameters {
+            backoff: Backoff {
+                initial_delay: Duration::from_millis(self.initial_delay_ms),
+                max_delay: Duration::from_millis(self.max_delay_ms),
+                multiplier: self.multiplier,
+                jitter: self.jitter,
+            },
+            max_retries: self.max_retries,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.initial_delay_ms > self.max_delay_ms {
+            return Err(ConfigError::Message(
+                "mqtt.reconnect.initial_delay_ms cannot be greater than mqtt.reconnect.max_delay_ms".to_string(),
+            ));
+        }
+
+        if self.multiplier < 1.0 {
+            return Err(ConfigError::Message("mqtt.reconnect.multiplier must be at least 1".to_string()));
+        }
+
+        if !(0.0..=1.0).contains(&self.jitter) {
+            return Err(ConfigError::Message("mqtt.reconnect.jitter must be between 0 and 1".to_string()));
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub struct ApiConfig {
+    /// Readings kept in memory per device for `/devices/{name}/history`
+    #[serde(default = "default_api_history_size")]
+    pub history_size: usize,
+    /// Events buffered per `/stream` or `/ws` client before dropping it
+    #[serde(default = "default_api_stream_buffer")]
+    pub stream_buffer: usize,
+    /// Milliseconds between keepalive pings sent to `/stream` and `/ws` clients
+    #[serde(default = "default_api_stream_keepalive_ms")]
+    pub stream_keepalive_ms: u64,
+    /// Origins allowed to call the API from a browser, `*` allowing any
+    #[serde(default = "default_api_cors_origins")]
+    pub cors_origins: Vec<String>,
+    #[serde(default)]
+    pub auth: AuthConfig,
+}
+
+impl Default for ApiConfig {
+    fn default() -> Self {
+        Self {
+            history_size: default_api_history_size(),
+            stream_buffer: default_api_stream_buffer(),
+            stream_keepalive_ms: default_api_stream_keepalive_ms(),
+            cors_origins: default_api_cors_origins(),
+            auth: AuthConfig::default(),
+        }
+    }
+}
+
+impl ApiConfig {
+    pub fn stream_keepalive(&self) -> Duration {
+        Duration::from_millis(self.stream_keepalive_ms)
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.stream_buffer == 0 {
+            return Err(ConfigError::Message("api.stream_buffer must be at least 1".to_string()));
+        }
+        if self.stream_keepalive_ms == 0 {
+            return Err(ConfigError::Message("api.stream_keepalive_ms must be at least 1".to_string()));
+        }
+        if self.cors_origins.iter().any(|origin| origin.is_empty()) {
+            return Err(ConfigError::Message("api.cors_origins cannot contain an empty origin".to_string()));
+        }
+        self.auth.validate()
+    }
+}
+
+/// Credentials required by the API. Every request is accepted when there are neither tokens nor users.
+#[derive(Clone, Debug, Default, serde::Deserialize, serde::Serialize)]
+pub struct AuthConfig {
+    /// Accepted as `Authorization: Bearer <token>`, or as the `access_token` query parameter
+    #[serde(default)]
+    pub tokens: Vec<String>,
+    /// htpasswd lines with a bcrypt hash, as written by `htpasswd -nbB <user> <password>`, accepted with basic auth.
+    /// The unsalted SHA-1 hashes of `htpasswd -nbs` are accepted too, but they are weak
+    #[serde(default)]
+    pub users: Vec<String>,
+    /// Serves `/health` without credentials
+    #[serde(default)]
+    pub open_health: bool,
+}
+
+impl AuthConfig {
+    /// The users and the hash of their password.
+    pub fn users(&self) -> Result<Vec<(String, PasswordHash)>, ConfigError> {
+        self.users
+            .iter()
+            .map(|line| parse_user(line).map_err(|e| ConfigError::Message(format!("Invalid api.auth.users entry: {}", e))))
+            .collect()
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.tokens.iter().any(|token| token.is_empty()) {
+            return Err(ConfigError::Message("api.auth.tokens cannot contain an empty token".to_string()));
+        }
+        self.users().map(|_| ())
+    }
+}
+
+const RETRY_ERROR_KINDS: [&str; 4] = ["network", "auth", "rejected", "backend"];
+
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub struct RetryConfig {
+    #[serde(default = "default_retry_max_attempts")]
+    pub max_attempts: u32,
+    #[serde(default = "default_retry_initial_delay_ms")]
+    pub initial_delay_ms: u64,
+    #[serde(default = "default_retry_max_delay_ms")]
+    pub max_delay_ms: u64,
+    #[serde(default = "default_retry_multiplier")]
+    pub multiplier: f64,
+    #[serde(default = "default_retry_jitter")]
+    pub jitter: f64,
+    pub retry_on: Option<Vec<String>>,
+}
+
+impl RetryConfig {
+    pub fn as_retry_parameters(&self) -> RetryParameters {
+        let classifier = match &self.retry_on {
+            Some(kinds) => {
+                let kinds = kinds.clone();
+                let classifier: RetryClassifier =
+                    Arc::new(move |e| SinkError::find(e).map(|e| kinds.iter().any(|k| k == e.kind())).unwrap_or(false));
+                classifier
+            }
+            None => default_retry_classifier(),
+        };
+        RetryParameters {
+            max_attempts: self.max_attempts,
+            backoff: Backoff {
+                initial_delay: Duration::from_millis(self.initial_delay_ms),
+                max_delay: Duration::from_millis(self.max_delay_ms),
+                multiplier: self.multiplier,
+                jitter: self.jitter,
+            },
+            classifier,
+        }
+    }
+
+    pub fn validate(&self, section: &str) -> Result<(), ConfigError> {
+        if self.max_attempts == 0 {
+            return Err(ConfigError::Message(format!("{}.max_attempts must be at least 1", section)));
+        }
+
+        if self.initial_delay_ms > self.max_delay_ms {
+            return Err(ConfigError::Message(format!(
+                "{section}.initial_delay_ms cannot be greater than {section}.max_delay_ms",
+                section = section
+            )));
+        }
+
+        if self.multiplier < 1.0 {
+            return Err(ConfigError::Message(format!("{}.multiplier must be at least 1", section)));
+        }
+
+        if !(0.0..=1.0).contains(&self.jitter) {
+            return Err(ConfigError::Message(format!("{}.jitter must be between 0 and 1", section)));
+        }
+
+        for kind in self.retry_on.iter().flatten() {
+            if !RETRY_ERROR_KINDS.contains(&kind.as_str()) {
+                return Err(ConfigError::Message(format!(
+                    "Unknown error kind [{}] in {}.retry_on, must be one of {:?}",
+                    kind, section, RETRY_ERROR_KINDS
+                )));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub struct QueueConfig {
+    #[serde(default = "default_queue_capacity")]
+    pub capacity: usize,
+    #[serde(default)]
+    pub overflow: OverflowPolicy,
+}
+
+impl QueueConfig {
+    pub fn as_queue_parameters(&self) -> QueueParameters {
+        QueueParameters {
+            capacity: self.capacity,
+            overflow: self.overflow,
+        }
+    }
+
+    pub fn validate(&self, section: &str) -> Result<(), ConfigError> {
+        if self.capacity == 0 {
+            return Err(ConfigError::Message(format!("{}.capacity must be at least 1", section)));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub struct TlsConnection {
+    pub ca_file: Option<String>,
+    pub client_cert_file: Option<String>,
+    pub client_key_file: Option<String>,
+    #[serde(default)]
+    pub insecure_skip_verify: bool,
+}
+
+impl TlsConnection {
+    pub fn as_tls_parameters(&self) -> MqttTlsParameters<'_> {
+        let client_auth = match (&self.client_cert_file, &self.client_key_file) {
+            (Some(cert_file), Some(key_file)) => Some(MqttClientAuth { cert_file, key_file }),
+            _ => None,
+        };
+        MqttTlsParameters {
+            ca_file: self.ca_file.as_deref(),
+            client_auth,
+            insecure_skip_verify: self.insecure_skip_verify,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if (self.client_cert_file.is_some() && self.client_key_file.is_none())
+            || (self.client_cert_file.is_none() && self.client_key_file.is_some())
+        {
+            return Err(ConfigError::Message(
+                "Either both or none mqtt.tls.client_cert_file and mqtt.tls.client_key_file must be defined".to_string(),
+            ));
+        }
+
+        self.as_tls_parameters()
+            .validate()
+            .map_err(|e| ConfigError::Message(format!("Invalid mqtt.tls configuration: {:#}", e)))
+    }
 }
 
 impl Connection {
+    /// The username and the password, an empty one being unset as in the original sample config.
+    fn credentials(&self) -> (Option<&str>, Option<&str>) {
+        let username = self.username.as_deref().filter(|username| !username.is_empty());
+        let password = self.password.as_deref().filter(|password| !password.is_empty());
+        (username, password)
+    }
+
+    pub fn as_connection_parameters<'a>(&'a self, client_id: &'a str) -> MqttConnectionParameters<'a> {
+        let credentials = match self.credentials() {
+            (Some(username), Some(password)) => Some(MqttCredentials { username, password }),
+            _ => None,
+        };
+        MqttConnectionParameters {
+            client_id,
+            host: &self.host,
+            port: self.port,
+            keep_alive: self.keep_alive,
+            clean_session: self.clean_session,
+            credentials,
+            tls: self.tls.as_ref().map(TlsConnection::as_tls_parameters),
+            reconnect: self.reconnect.as_reconnect_parameters(),
+        }
+    }
+
     pub fn validate(&self) -> Result<(), ConfigError> {
         if self.host.is_empty() {
             return Err(ConfigError::Message("connection.host cannot be empty".to_string()));
         }
+
+        if self.keep_alive < 5 {
+            return Err(ConfigError::Message("mqtt.keep_alive must be at least 5 seconds".to_string()));
+        }
+
+        let (username, password) = self.credentials();
+        if username.is_some() != password.is_some() {
+            return Err(ConfigError::Message(
+                "Either both or none mqtt.username and mqtt.password must be defined".to_string(),
+            ));
+        }
+
+        if let Some(tls) = &self.tls {
+            tls.validate()?;
+        }
+        self.reconnect.validate()?;
+
         Ok(())
     }
 }
@@ -43,21 +465,75 @@ impl Connection {
 #[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
 pub struct InfluxDbConnection {
     pub server: String,
-    pub database: String,
+    #[serde(default = "default_influx_version")]
+    pub version: u8,
+    pub database: Option<String>,
     pub username: Option<String>,
     pub password: Option<String>,
+    pub org: Option<String>,
+    pub bucket: Option<String>,
+    pub token: Option<String>,
+    /// Measurement of the subscriptions that do not set one
+    #[serde(default = "default_influx_measurement")]
+    pub measurement: String,
+    /// Retention policy of the subscriptions that do not set one, v1 only
+    pub retention_policy: Option<String>,
+    #[serde(default)]
+    pub exclude_fields: Vec<String>,
+    #[serde(default = "default_influx_batch_size")]
+    pub batch_size: usize,
+    #[serde(default = "default_influx_flush_interval_ms")]
+    pub flush_interval_ms: u64,
+    pub buffer_dir: Option<String>,
+    #[serde(default = "default_influx_buffer_max_mb")]
+    pub buffer_max_mb: u64,
+    #[serde(default = "default_influx_buffer_replay_interval_ms")]
+    pub buffer_replay_interval_ms: u64,
+    /// Only used by the `[influx]` shorthand, `[[sinks]]` entries set them next to their type
+    pub retry: Option<RetryConfig>,
+    pub queue: Option<QueueConfig>,
 }
 
 impl InfluxDbConnection {
-    pub fn as_connection_parameters(&self) -> InfluxDbConnectionParameters {
-        let credentials = match (&self.username, &self.password) {
-            (Some(username), Some(password)) => Some(InfluxDbCredentials { username, password }),
-            _ => None,
+    /// Parses the options of an influx sink.
+    pub fn from_options(options: &Value) -> Result<Self, serde_json::Error> {
+        serde_json::from_value(options.clone())
+    }
+
+    pub fn as_connection_parameters(&self) -> InfluxDbConnectionParameters<'_> {
+        let api = match self.version {
+            2 => InfluxDbApi::V2 {
+                org: self.org.as_deref().unwrap_or_default(),
+                bucket: self.bucket.as_deref().unwrap_or_default(),
+                token: self.token.as_deref().unwrap_or_default(),
+            },
+            _ => {
+                let credentials = match (&self.username, &self.password) {
+                    (Some(username), Some(password)) => Some(InfluxDbCredentials { username, password }),
+                    _ => None,
+                };
+                InfluxDbApi::V1 {
+                    db: self.database.as_deref().unwrap_or_default(),
+                    credentials,
+                }
+            }
         };
         InfluxDbConnectionParameters {
             server: &self.server,
-            db: &self.database,
-            credentials,
+            api,
+            measurement: &self.measurement,
+            retention_policy: self.retention_policy.as_deref(),
+            exclude_fields: &self.exclude_fields,
+            batch: InfluxDbBatchParameters {
+                batch_size: self.batch_size,
+                flush_interval: Duration::from_millis(self.flush_interval_ms),
+            },
+            retry: None,
+            buffer: self.buffer_dir.as_ref().map(|dir| InfluxDbBufferParameters {
+                dir,
+                max_bytes: self.buffer_max_mb * 1024 * 1024,
+                replay_interval: Duration::from_millis(self.buffer_replay_interval_ms),
+            }),
         }
     }
 
@@ -66,16 +542,144 @@ impl InfluxDbConnection {
             return Err(ConfigError::Message("influxdb.server cannot be empty".to_string()));
         }
 
-        if self.database.is_empty() {
-            return Err(ConfigError::Message("influxdb.server cannot be empty".to_string()));
+        if self.measurement.is_empty() {
+            return Err(ConfigError::Message("influxdb.measurement cannot be empty".to_string()));
+        }
+
+        if self.batch_size == 0 {
+            return Err(ConfigError::Message("influxdb.batch_size must be at least 1".to_string()));
         }
 
-        if (self.username.is_some() && self.password.is_none()) || (self.username.is_none() && self.password.is_some()) {
+        if self.flush_interval_ms == 0 {
+            return Err(ConfigError::Message("influxdb.flush_interval_ms must be at least 1".to_string()));
+        }
+
+        if self.buffer_max_mb == 0 {
+            return Err(ConfigError::Message("influxdb.buffer_max_mb must be at least 1".to_string()));
+        }
+
+        if self.buffer_replay_interval_ms == 0 {
             return Err(ConfigError::Message(
-                "Either both or none influxdb.username and influxdb.password must be defined".to_string(),
+                "influxdb.buffer_replay_interval_ms must be at least 1".to_string(),
             ));
         }
 
+        if let Some(retention_policy) = &self.retention_policy {
+            validate_retention_policy(retention_policy)
+                .map_err(|e| ConfigError::Message(format!("Invalid influxdb.retention_policy: {}", e)))?;
+        }
+
+        match self.version {
+            1 => {
+                if self.database.as_deref().unwrap_or_default().is_empty() {
+                    return Err(ConfigError::Message("influxdb.database cannot be empty".to_string()));
+                }
+
+                if (self.username.is_some() && self.password.is_none()) || (self.username.is_none() && self.password.is_some()) {
+                    return Err(ConfigError::Message(
+                        "Either both or none influxdb.username and influxdb.password must be defined".to_string(),
+                    ));
+                }
+            }
+            2 => {
+                if self.retention_policy.is_some() {
+                    return Err(ConfigError::Message(
+                        "influxdb.retention_policy is not supported when influxdb.version is 2".to_string(),
+                    ));
+                }
+                for (name, value) in &[("org", &self.org), ("bucket", &self.bucket), ("token", &self.token)] {
+                    if value.as_deref().unwrap_or_default().is_empty() {
+                        return Err(ConfigError::Message(format!(
+                            "influxdb.{} cannot be empty when influxdb.version is 2",
+                            name
+                        )));
+                    }
+                }
+            }
+            v => {
+                return Err(ConfigError::Message(format!(
+                    "Unsupported influxdb.version [{}], must be 1 or 2",
+                    v
+                )))
+            }
+        }
+
+        Ok(())
+    }
+}
+
+const INFLUX_SINK_TYPE: &str = "influx";
+const API_SINK_TYPE: &str = "api";
+
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub struct SinkConfig {
+    /// Registered sink type, such as influx, api or log
+    #[serde(rename = "type")]
+    pub sink_type: String,
+    /// Name used in logs and errors, the type by default
+    pub name: Option<String>,
+    pub retry: Option<RetryConfig>,
+    pub queue: Option<QueueConfig>,
+    /// Events the sink receives, all of them by default
+    pub filter: Option<EventFilter>,
+    /// Type specific options
+    #[serde(flatten)]
+    pub options: Map<String, Value>,
+}
+
+impl SinkConfig {
+    fn new(sink_type: &str) -> Self {
+        Self {
+            sink_type: sink_type.to_string(),
+            name: None,
+            retry: None,
+            queue: None,
+            filter: None,
+            options: Map::new(),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        self.name.as_deref().unwrap_or(&self.sink_type)
+    }
+
+    pub fn as_sink_spec(&self) -> SinkSpec {
+        SinkSpec {
+            name: self.name().to_string(),
+            sink_type: self.sink_type.clone(),
+            options: Value::Object(self.options.clone()),
+            retry: self.retry.as_ref().map(RetryConfig::as_retry_parameters),
+            queue: self.queue.as_ref().map(QueueConfig::as_queue_parameters),
+            filter: self.filter.clone(),
+        }
+    }
+
+    pub fn validate(&self, subscriptions: &HashMap<String, Subscription>) -> Result<(), ConfigError> {
+        let section = format!("sinks.{}", self.name());
+        if let Some(retry) = &self.retry {
+            retry.validate(&format!("{}.retry", section))?;
+        }
+        if let Some(queue) = &self.queue {
+            queue.validate(&format!("{}.queue", section))?;
+        }
+        if self.sink_type != INFLUX_SINK_TYPE {
+            return Ok(());
+        }
+
+        let influx = InfluxDbConnection::from_options(&Value::Object(self.options.clone()))
+            .map_err(|e| ConfigError::Message(format!("Invalid {}: {}", section, e)))?;
+        influx
+            .validate()
+            .map_err(|e| ConfigError::Message(format!("Invalid {}: {}", section, e)))?;
+        if influx.version == 2 {
+            if let Some((name, _)) = subscriptions.iter().find(|(_, s)| s.retention_policy.is_some()) {
+                return Err(ConfigError::Message(format!(
+                    "subscriptions.{}.retention_policy is not supported by InfluxDB version 2 sink [{}]",
+                    name,
+                    self.name()
+                )));
+            }
+        }
         Ok(())
     }
 }
@@ -89,23 +693,94 @@ pub struct Config {
     #[serde(default = "default_client_id")]
     pub client_id: String,
     pub mqtt: Connection,
+    #[serde(default)]
+    pub api: ApiConfig,
     pub subscriptions: HashMap<String, Subscription>,
-    pub influx: InfluxDbConnection,
+    /// Shorthand for an influx sink followed by an api sink, when there is no `[[sinks]]`
+    pub influx: Option<InfluxDbConnection>,
+    #[serde(default)]
+    pub sinks: Vec<SinkConfig>,
+    /// When an event is considered stored: all, best_effort or { quorum = n }
+    #[serde(default)]
+    pub sink_policy: SinkSetPolicy,
+    /// Time given to drain the pending events and flush the sinks on SIGTERM/SIGINT before giving up
+    #[serde(default = "default_shutdown_timeout_ms")]
+    pub shutdown_timeout_ms: u64,
 }
 
 impl Config {
+    pub fn shutdown_timeout(&self) -> Duration {
+        Duration::from_millis(self.shutdown_timeout_ms)
+    }
+
     pub fn validate(&self) -> Result<(), ConfigError> {
         if self.subscriptions.is_empty() {
             return Err(ConfigError::Message("Subscription list cannot be empty".to_string()));
         }
+        for (name, subscription) in self.subscriptions.iter() {
+            subscription
+                .validate()
+                .map_err(|e| ConfigError::Message(format!("Invalid subscriptions.{}: {}", name, e)))?;
+        }
         self.mqtt.validate()?;
-        self.influx.validate()?;
+        self.api.validate()?;
+
+        if self.influx.is_some() && !self.sinks.is_empty() {
+            return Err(ConfigError::Message(
+                "[influx] cannot be combined with [[sinks]], declare it as a sink of type influx".to_string(),
+            ));
+        }
+        let sinks = self.sink_configs();
+        if sinks.is_empty() {
+            return Err(ConfigError::Message("At least one sink must be defined in [[sinks]]".to_string()));
+        }
+        let mut names = Vec::new();
+        for sink in sinks.iter() {
+            if names.contains(&sink.name()) {
+                return Err(ConfigError::Message(format!(
+                    "Duplicated sink name [{}], set a different name on sinks of the same type",
+                    sink.name()
+                )));
+            }
+            names.push(sink.name());
+            sink.validate(&self.subscriptions)?;
+        }
+        if let SinkSetPolicy::Quorum(quorum) = self.sink_policy {
+            if quorum == 0 || quorum > sinks.len() {
+                return Err(ConfigError::Message(format!(
+                    "sink_policy quorum must be between 1 and the number of sinks [{}]",
+                    sinks.len()
+                )));
+            }
+        }
         Ok(())
     }
 
     pub fn subscriptions(&self) -> Vec<Subscription> {
         self.subscriptions.values().cloned().collect()
     }
+
+    /// The `[[sinks]]` entries, or the sinks `[influx]` stands for.
+    pub fn sink_configs(&self) -> Vec<SinkConfig> {
+        if !self.sinks.is_empty() {
+            return self.sinks.clone();
+        }
+        let influx = match &self.influx {
+            Some(influx) => influx,
+            None => return Vec::new(),
+        };
+        let options = match serde_json::to_value(influx) {
+            Ok(Value::Object(options)) => options,
+            _ => Map::new(),
+        };
+        let influx_sink = SinkConfig {
+            retry: influx.retry.clone(),
+            queue: influx.queue.clone(),
+            options,
+            ..SinkConfig::new(INFLUX_SINK_TYPE)
+        };
+        vec![influx_sink, SinkConfig::new(API_SINK_TYPE)]
+    }
 }
 
 pub fn load(path: Option<&str>) -> Result<Config, ConfigError> {
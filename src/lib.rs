@@ -0,0 +1,7 @@
+#[macro_use]
+extern crate tracing;
+
+pub mod api;
+pub mod conf;
+pub mod sinks;
+pub mod utils;
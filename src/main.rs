@@ -2,13 +2,11 @@
 extern crate tracing;
 
 use clap::{App as ClapApp, Arg};
-use mqtt2influx_core::{Executor, InfluxDbSink, MqttConnectionParameters, MqttEventSource, SinkTee};
+use mqtt2influx::{api, conf, sinks, utils};
+use mqtt2influx_core::utils::Backoff;
+use mqtt2influx_core::{DeviceMonitor, DeviceStatuses, EventSink, Executor, Metrics, MqttEventSource, Shutdown};
 use std::sync::Arc;
 
-mod api;
-mod conf;
-mod utils;
-
 const VERSION: &str = git_version::git_version!(args = ["--tags", "--always", "--abbrev=1", "--dirty=-modified"]);
 const CONFIG_PATH_ARG: &str = "config";
 
@@ -32,32 +30,98 @@ async fn main() {
     let configuration = conf::load(config_path).expect("Could not load the configuration");
     utils::setup_logging(&configuration.log_level);
 
+    let shutdown = Shutdown::default();
+    let metrics = Metrics::default();
     let source = MqttEventSource::new(
-        MqttConnectionParameters {
-            client_id: &configuration.client_id,
-            host: &configuration.mqtt.host,
-            port: configuration.mqtt.port,
-        },
+        configuration.mqtt.as_connection_parameters(&configuration.client_id),
         configuration.subscriptions(),
-    );
+    )
+    .expect("Error creating MqttEventSource")
+    .with_shutdown(shutdown.clone())
+    .with_metrics(metrics.clone());
 
-    let influx_sink = InfluxDbSink::new(configuration.influx.as_connection_parameters())
+    let statuses = DeviceStatuses::default();
+    let api_sink =
+        Arc::new(api::ApiState::new(configuration.api.history_size, configuration.api.stream_buffer).with_statuses(statuses.clone()));
+    let graph = sinks::registry(api_sink.clone())
+        .with_metrics(metrics.clone())
+        .build(
+            configuration.sink_configs().iter().map(conf::SinkConfig::as_sink_spec).collect(),
+            configuration.sink_policy,
+        )
         .await
-        .expect("Error creating InfluxDbSink");
-    let api_sink = Arc::new(api::ApiState::default());
-    let tee = SinkTee::new(Arc::new(influx_sink), api_sink.clone());
+        .expect("Error creating sinks");
+    let sinks = Arc::new(DeviceMonitor::new(configuration.subscriptions(), Arc::new(graph.sinks), statuses));
+    tokio::spawn(sinks.clone().run(shutdown.clone()));
 
-    tokio::spawn(async move {
+    let executor_shutdown = shutdown.clone();
+    let executor_metrics = metrics.clone();
+    let executor = tokio::spawn(async move {
         info!("Executor started");
-        if let Err(e) = Executor::run(source, &tee).await {
+        let res = Executor::run_with_retries(source, &*sinks, &executor_metrics, &Backoff::default(), &executor_shutdown).await;
+        // Stores the events still buffered by the sinks
+        let closed = sinks.close().await;
+        if let Err(e) = res {
             error!("[Executor] Fatal error: {}", e);
             std::process::exit(1);
         }
+        if !executor_shutdown.is_triggered() {
+            // The source only stops producing events once it has given up reconnecting or is shut down
+            error!("[Executor] Event source finished");
+            std::process::exit(1);
+        }
+        closed
     });
 
     info!("Application started: [{}]", VERSION);
-    if let Err(e) = api::run(configuration.port, api_sink).await {
-        error!("[API] Fatal error: {}", e);
-        std::process::exit(1);
+    let server = match api::server(configuration.port, configuration.api.clone(), api_sink, graph.queues, metrics) {
+        Ok(server) => server,
+        Err(e) => {
+            error!("[API] Fatal error: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let server_handle = server.handle();
+    let mut server = tokio::spawn(server);
+
+    tokio::select! {
+        res = &mut server => {
+            match res {
+                Ok(Err(e)) => error!("[API] Fatal error: {}", e),
+                Err(e) => error!("[API] Fatal error: {}", e),
+                Ok(Ok(())) => error!("[API] Server stopped"),
+            }
+            std::process::exit(1);
+        }
+        signal = utils::wait_for_signal() => match signal {
+            Ok(signal) => info!("Received {}, shutting down", signal),
+            Err(e) => {
+                error!("Error listening for signals: {}", e);
+                std::process::exit(1);
+            }
+        },
+    }
+
+    shutdown.trigger();
+    let stopped = tokio::time::timeout(configuration.shutdown_timeout(), async move {
+        let closed = executor.await;
+        server_handle.stop(true).await;
+        closed
+    })
+    .await;
+    match stopped {
+        Ok(Ok(Ok(()))) => info!("Shutdown complete"),
+        Ok(Ok(Err(e))) => {
+            error!("Error flushing sinks on shutdown: {}", e);
+            std::process::exit(1);
+        }
+        Ok(Err(e)) => {
+            error!("[Executor] Fatal error: {}", e);
+            std::process::exit(1);
+        }
+        Err(_) => {
+            error!("Shutdown did not complete within {} ms, exiting", configuration.shutdown_timeout_ms);
+            std::process::exit(1);
+        }
     }
 }
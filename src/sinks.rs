@@ -0,0 +1,25 @@
+use crate::api::ApiState;
+use crate::conf::InfluxDbConnection;
+use mqtt2influx_core::anyhow::Context;
+use mqtt2influx_core::{EventSink, InfluxDbConnectionParameters, InfluxDbSink, SinkRegistry, SinkSpec};
+use std::sync::Arc;
+
+/// Registry with the core sink types plus `influx` and `api`. Every api sink feeds the state served by the API.
+pub fn registry(api_state: Arc<ApiState>) -> SinkRegistry {
+    let mut registry = SinkRegistry::default();
+    registry.register("api", move |_| {
+        let api_state = api_state.clone();
+        async move { Ok(api_state as Arc<dyn EventSink>) }
+    });
+    // Retries whole batches, see `InfluxDbConnectionParameters::retry`
+    registry.register_retrying("influx", |spec: SinkSpec| async move {
+        let influx = InfluxDbConnection::from_options(&spec.options).context("Invalid influx sink options")?;
+        let sink = InfluxDbSink::new(InfluxDbConnectionParameters {
+            retry: spec.retry,
+            ..influx.as_connection_parameters()
+        })
+        .await?;
+        Ok(Arc::new(sink) as Arc<dyn EventSink>)
+    });
+    registry
+}
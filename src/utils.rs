@@ -24,3 +24,12 @@ pub fn setup_logging(log_level: &str) {
 
     tracing::subscriber::set_global_default(subscriber).unwrap();
 }
+
+/// Resolves on the first SIGTERM or SIGINT.
+pub async fn wait_for_signal() -> std::io::Result<&'static str> {
+    let mut sigterm = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())?;
+    tokio::select! {
+        _ = sigterm.recv() => Ok("SIGTERM"),
+        res = tokio::signal::ctrl_c() => res.map(|_| "SIGINT"),
+    }
+}
@@ -0,0 +1,156 @@
+use actix_web::http::{header, StatusCode};
+use actix_web::test::{self, TestRequest};
+use actix_web::{web, App, HttpResponse};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use mqtt2influx::api::{parse_user, Auth, PasswordHash};
+use sha1::{Digest, Sha1};
+
+const TOKEN: &str = "a+b/c=";
+
+fn sha_user(user: &str, password: &str) -> (String, PasswordHash) {
+    let line = format!("{}:{{SHA}}{}", user, STANDARD.encode(Sha1::digest(password.as_bytes())));
+    parse_user(&line).expect("Should be able to parse a {SHA} user")
+}
+
+fn bcrypt_user(user: &str, password: &str) -> (String, PasswordHash) {
+    // htpasswd -B writes the $2y$ variant
+    let hash = bcrypt::hash_with_result(password, 4)
+        .unwrap()
+        .format_for_version(bcrypt::Version::TwoY);
+    parse_user(&format!("{}:{}", user, hash)).expect("Should be able to parse a bcrypt user")
+}
+
+fn basic(user: &str, password: &str) -> (header::HeaderName, String) {
+    (
+        header::AUTHORIZATION,
+        format!("Basic {}", STANDARD.encode(format!("{}:{}", user, password))),
+    )
+}
+
+async fn status(auth: Auth, req: TestRequest) -> StatusCode {
+    let app = test::init_service(
+        App::new()
+            .wrap(auth)
+            .route("/health", web::get().to(HttpResponse::Ok))
+            .route("/values", web::get().to(HttpResponse::Ok)),
+    )
+    .await;
+    test::call_service(&app, req.to_request()).await.status()
+}
+
+fn token_auth() -> Auth {
+    Auth::new(vec![TOKEN.to_string()], vec![], vec![])
+}
+
+#[actix_web::test]
+async fn every_request_passes_without_credentials_configured() {
+    let auth = Auth::new(vec![], vec![], vec![]);
+
+    assert_eq!(status(auth, TestRequest::get().uri("/values")).await, StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn unauthorized_requests_get_a_401_with_the_matching_challenge() {
+    let app = test::init_service(App::new().wrap(token_auth()).route("/values", web::get().to(HttpResponse::Ok))).await;
+    let res = test::call_service(&app, TestRequest::get().uri("/values").to_request()).await;
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(
+        res.headers().get(header::WWW_AUTHENTICATE).unwrap(),
+        "Bearer",
+        "Tokens only should ask for a bearer"
+    );
+
+    let auth = Auth::new(vec![], vec![sha_user("admin", "secret")], vec![]);
+    let app = test::init_service(App::new().wrap(auth).route("/values", web::get().to(HttpResponse::Ok))).await;
+    let res = test::call_service(&app, TestRequest::get().uri("/values").to_request()).await;
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(
+        res.headers().get(header::WWW_AUTHENTICATE).unwrap(),
+        "Basic realm=\"mqtt2influx\"",
+        "Users should ask for basic auth"
+    );
+}
+
+#[actix_web::test]
+async fn bearer_tokens_are_checked() {
+    let valid = TestRequest::get()
+        .uri("/values")
+        .insert_header((header::AUTHORIZATION, format!("Bearer {}", TOKEN)));
+    assert_eq!(status(token_auth(), valid).await, StatusCode::OK);
+
+    let invalid = TestRequest::get()
+        .uri("/values")
+        .insert_header((header::AUTHORIZATION, "Bearer wrong"));
+    assert_eq!(status(token_auth(), invalid).await, StatusCode::UNAUTHORIZED);
+
+    let unknown_scheme = TestRequest::get()
+        .uri("/values")
+        .insert_header((header::AUTHORIZATION, format!("Token {}", TOKEN)));
+    assert_eq!(status(token_auth(), unknown_scheme).await, StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn basic_auth_is_checked_against_sha_and_bcrypt_users() {
+    let users = || vec![sha_user("sha", "secret"), bcrypt_user("bcrypt", "secret")];
+
+    for user in &["sha", "bcrypt"] {
+        let valid = TestRequest::get().uri("/values").insert_header(basic(user, "secret"));
+        assert_eq!(
+            status(Auth::new(vec![], users(), vec![]), valid).await,
+            StatusCode::OK,
+            "[{}] should pass",
+            user
+        );
+
+        let invalid = TestRequest::get().uri("/values").insert_header(basic(user, "wrong"));
+        assert_eq!(
+            status(Auth::new(vec![], users(), vec![]), invalid).await,
+            StatusCode::UNAUTHORIZED,
+            "[{}] with a wrong password should not pass",
+            user
+        );
+    }
+    let unknown = TestRequest::get().uri("/values").insert_header(basic("nobody", "secret"));
+    assert_eq!(status(Auth::new(vec![], users(), vec![]), unknown).await, StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn access_tokens_are_percent_decoded() {
+    let encoded = TestRequest::get().uri("/values?since=1&access_token=a%2Bb%2Fc%3D");
+    assert_eq!(status(token_auth(), encoded).await, StatusCode::OK);
+
+    let invalid = TestRequest::get().uri("/values?access_token=wrong");
+    assert_eq!(status(token_auth(), invalid).await, StatusCode::UNAUTHORIZED);
+
+    // An unencoded `+` is a space
+    let unencoded = TestRequest::get().uri("/values?access_token=a+b/c=");
+    assert_eq!(status(token_auth(), unencoded).await, StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn open_paths_are_served_without_credentials() {
+    let open = || Auth::new(vec![TOKEN.to_string()], vec![], vec!["/health".to_string()]);
+
+    assert_eq!(status(open(), TestRequest::get().uri("/health")).await, StatusCode::OK);
+    assert_eq!(status(open(), TestRequest::get().uri("/values")).await, StatusCode::UNAUTHORIZED);
+    assert_eq!(
+        status(token_auth(), TestRequest::get().uri("/health")).await,
+        StatusCode::UNAUTHORIZED
+    );
+}
+
+#[test]
+fn unsupported_or_malformed_users_are_rejected() {
+    for line in &[
+        "admin",
+        ":{SHA}5en6G6MezRroT3XKqkdPOmY/BfQ=",
+        "admin:secret",
+        "admin:$apr1$salt$hash",
+        "admin:{SHA}not base64",
+        "admin:{SHA}c2hvcnQ=",
+        "admin:$2y$04$tooshort",
+    ] {
+        assert!(parse_user(line).is_err(), "[{}] should be rejected", line);
+    }
+}
@@ -0,0 +1,182 @@
+use crate::test_tools::*;
+use mqtt2influx::api::ApiState;
+use mqtt2influx::conf::{InfluxDbConnection, SinkConfig};
+use mqtt2influx::sinks;
+use mqtt2influx_core::serde_json::{self, Value};
+use std::sync::Arc;
+
+const SUBSCRIPTIONS: &str = r#"
+[subscriptions.room]
+topic = "some/topic/room"
+device_name = "Room"
+
+[influx]
+server = "http://127.0.0.1:8086"
+database = "my_database"
+"#;
+
+fn mqtt_config(credentials: &str) -> String {
+    format!("[mqtt]\nhost = \"localhost\"\nport = 1883\n{}\n{}", credentials, SUBSCRIPTIONS)
+}
+
+#[test]
+fn empty_mqtt_credentials_are_unset() {
+    let config = parse_config(&mqtt_config("username = \"\"\npassword = \"\"")).expect("Sample credentials should be valid");
+
+    let parameters = config.mqtt.as_connection_parameters("client");
+    assert!(parameters.credentials.is_none(), "Empty credentials should not be sent");
+}
+
+#[test]
+fn mqtt_credentials_are_sent_when_both_are_set() {
+    let config = parse_config(&mqtt_config("username = \"user\"\npassword = \"secret\"")).unwrap();
+
+    let parameters = config.mqtt.as_connection_parameters("client");
+    let credentials = parameters.credentials.expect("Credentials should be sent");
+    assert_eq!((credentials.username, credentials.password), ("user", "secret"));
+}
+
+#[test]
+fn a_username_without_password_is_rejected() {
+    for credentials in &[
+        "username = \"user\"",
+        "username = \"user\"\npassword = \"\"",
+        "password = \"secret\"",
+    ] {
+        let err = parse_config(&mqtt_config(credentials)).expect_err("Credentials should be rejected");
+        assert!(err.to_string().contains("mqtt.username"), "[{}] gave [{}]", credentials, err);
+    }
+}
+
+const MQTT: &str = r#"
+[mqtt]
+host = "localhost"
+port = 1883
+
+[subscriptions.room]
+topic = "some/topic/room"
+device_name = "Room"
+"#;
+
+/// Sinks of `config` as JSON, with the influx options parsed so that unset and default options compare equal.
+fn normalized_sinks(config: &str) -> Vec<Value> {
+    let config = parse_config(&format!("{}{}", MQTT, config)).expect("Config should be valid");
+    config
+        .sink_configs()
+        .into_iter()
+        .map(|mut sink| {
+            if sink.sink_type == "influx" {
+                let influx = InfluxDbConnection::from_options(&Value::Object(sink.options)).expect("Influx options should parse");
+                sink.options = match serde_json::to_value(influx).unwrap() {
+                    Value::Object(options) => options,
+                    _ => unreachable!(),
+                };
+                // Only read from the [influx] shorthand, sinks take them from their own retry and queue
+                sink.options.remove("retry");
+                sink.options.remove("queue");
+            }
+            serde_json::to_value(sink).unwrap()
+        })
+        .collect()
+}
+
+fn sinks_error(sinks: &str) -> String {
+    parse_config(&format!("{}{}", MQTT, sinks))
+        .expect_err("Config should be rejected")
+        .to_string()
+}
+
+#[test]
+fn influx_section_is_an_influx_sink_followed_by_an_api_sink() {
+    let legacy = normalized_sinks(
+        r#"
+[influx]
+server = "http://127.0.0.1:8086"
+database = "my_database"
+batch_size = 100
+
+[influx.retry]
+max_attempts = 3
+"#,
+    );
+    let sinks = normalized_sinks(
+        r#"
+[[sinks]]
+type = "influx"
+server = "http://127.0.0.1:8086"
+database = "my_database"
+batch_size = 100
+retry = { max_attempts = 3 }
+
+[[sinks]]
+type = "api"
+"#,
+    );
+    assert_eq!(legacy, sinks, "[influx] should stand for the equivalent [[sinks]]");
+}
+
+#[test]
+fn influx_section_cannot_be_combined_with_sinks() {
+    let err = sinks_error(
+        r#"
+[influx]
+server = "http://127.0.0.1:8086"
+database = "my_database"
+
+[[sinks]]
+type = "api"
+"#,
+    );
+    assert!(err.contains("[influx] cannot be combined with [[sinks]]"), "Got [{}]", err);
+}
+
+#[test]
+fn invalid_influx_sinks_are_rejected() {
+    for (options, message) in &[
+        ("database = \"\"", "influxdb.database cannot be empty"),
+        ("database = \"db\"\nbatch_size = 0", "influxdb.batch_size must be at least 1"),
+        (
+            "database = \"db\"\nflush_interval_ms = 0",
+            "influxdb.flush_interval_ms must be at least 1",
+        ),
+    ] {
+        let err = sinks_error(&format!(
+            "[[sinks]]\ntype = \"influx\"\nserver = \"http://127.0.0.1:8086\"\n{}",
+            options
+        ));
+        assert!(err.contains(message), "[{}] gave [{}]", options, err);
+    }
+}
+
+#[actix_web::test]
+async fn unknown_sink_types_are_rejected() {
+    let config = parse_config(&format!("{}[[sinks]]\ntype = \"kafka\"\n", MQTT)).expect("Config should parse");
+
+    let specs = config.sink_configs().iter().map(SinkConfig::as_sink_spec).collect();
+    let err = match sinks::registry(Arc::new(ApiState::new(10, 16)))
+        .build(specs, config.sink_policy)
+        .await
+    {
+        Ok(_) => panic!("Unknown sink type should be rejected"),
+        Err(e) => format!("{:#}", e),
+    };
+    assert!(err.contains("Unknown type [kafka]"), "Got [{}]", err);
+}
+
+#[test]
+fn misspelled_filter_keys_are_rejected() {
+    for filter in &[
+        "[sinks.filter.include]\ndevice = [\"kitchen\"]",
+        "[sinks.filter]\nexlude = { devices = [\"kitchen\"] }",
+    ] {
+        let err = sinks_error(&format!("[[sinks]]\ntype = \"log\"\n{}", filter));
+        assert!(err.contains("unknown field"), "[{}] gave [{}]", filter, err);
+    }
+
+    let config = parse_config(&format!(
+        "{}[[sinks]]\ntype = \"log\"\n[sinks.filter.exclude]\ndevices = [\"kitchen\"]",
+        MQTT
+    ))
+    .expect("Known filter keys should be accepted");
+    assert!(config.sinks[0].filter.is_some(), "Filter should be set");
+}
@@ -0,0 +1,94 @@
+use actix_web::body::BoxBody;
+use actix_web::dev::ServiceResponse;
+use actix_web::http::{header, Method, StatusCode};
+use actix_web::test::{self, TestRequest};
+use actix_web::{web, App, HttpResponse};
+use mqtt2influx::api::{Auth, Cors};
+
+const ORIGIN: &str = "https://dashboard.example.com";
+
+/// Calls a `/values` route behind the CORS and token middlewares, in the order the server wraps them.
+async fn call(origins: &[&str], req: TestRequest) -> ServiceResponse<BoxBody> {
+    let app = test::init_service(
+        App::new()
+            .wrap(Auth::new(vec!["token".to_string()], vec![], vec![]))
+            .wrap(Cors::new(origins.iter().map(|o| o.to_string()).collect()))
+            .route("/values", web::get().to(HttpResponse::Ok)),
+    )
+    .await;
+    test::call_service(&app, req.to_request()).await.map_into_boxed_body()
+}
+
+fn authorized(origin: &str) -> TestRequest {
+    TestRequest::get()
+        .uri("/values")
+        .insert_header((header::ORIGIN, origin))
+        .insert_header((header::AUTHORIZATION, "Bearer token"))
+}
+
+fn preflight(origin: &str) -> TestRequest {
+    TestRequest::default()
+        .method(Method::OPTIONS)
+        .uri("/values")
+        .insert_header((header::ORIGIN, origin))
+        .insert_header((header::ACCESS_CONTROL_REQUEST_METHOD, "GET"))
+        .insert_header((header::ACCESS_CONTROL_REQUEST_HEADERS, "authorization"))
+}
+
+#[actix_web::test]
+async fn any_origin_is_allowed_without_vary() {
+    let res = call(&["*"], authorized(ORIGIN)).await;
+
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers().get(header::ACCESS_CONTROL_ALLOW_ORIGIN).unwrap(), "*");
+    assert!(
+        res.headers().get(header::VARY).is_none(),
+        "Response should not depend on the origin"
+    );
+}
+
+#[actix_web::test]
+async fn allowed_origins_are_echoed_with_vary() {
+    let res = call(&["https://other.example.com", ORIGIN], authorized(ORIGIN)).await;
+
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers().get(header::ACCESS_CONTROL_ALLOW_ORIGIN).unwrap(), ORIGIN);
+    assert_eq!(res.headers().get(header::VARY).unwrap(), "Origin");
+}
+
+#[actix_web::test]
+async fn other_origins_get_no_allow_origin() {
+    let res = call(&[ORIGIN], authorized("https://evil.example.com")).await;
+
+    assert_eq!(res.status(), StatusCode::OK, "Request should still be served");
+    assert!(res.headers().get(header::ACCESS_CONTROL_ALLOW_ORIGIN).is_none());
+    assert_eq!(res.headers().get(header::VARY).unwrap(), "Origin");
+}
+
+#[actix_web::test]
+async fn preflights_are_answered_before_authentication() {
+    let res = call(&[ORIGIN], preflight(ORIGIN)).await;
+
+    assert_eq!(res.status(), StatusCode::NO_CONTENT);
+    assert_eq!(res.headers().get(header::ACCESS_CONTROL_ALLOW_ORIGIN).unwrap(), ORIGIN);
+    assert_eq!(res.headers().get(header::ACCESS_CONTROL_ALLOW_METHODS).unwrap(), "GET");
+    assert_eq!(res.headers().get(header::ACCESS_CONTROL_ALLOW_HEADERS).unwrap(), "Authorization");
+    assert_eq!(res.headers().get(header::VARY).unwrap(), "Origin");
+
+    let res = call(&[ORIGIN], preflight("https://evil.example.com")).await;
+    assert_eq!(res.status(), StatusCode::NO_CONTENT);
+    assert!(res.headers().get(header::ACCESS_CONTROL_ALLOW_ORIGIN).is_none());
+}
+
+#[actix_web::test]
+async fn unauthorized_responses_carry_the_allow_origin() {
+    let req = TestRequest::get().uri("/values").insert_header((header::ORIGIN, ORIGIN));
+    let res = call(&[ORIGIN], req).await;
+
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(
+        res.headers().get(header::ACCESS_CONTROL_ALLOW_ORIGIN).unwrap(),
+        ORIGIN,
+        "Browsers should be able to read the 401"
+    );
+}
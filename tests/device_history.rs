@@ -0,0 +1,109 @@
+use crate::test_tools::*;
+use actix_web::http::StatusCode;
+use actix_web::test;
+use mqtt2influx::api::{ApiEvent, ApiState};
+use mqtt2influx_core::serde_json::{self, json, Value};
+use mqtt2influx_core::EventSink;
+use std::sync::Arc;
+
+async fn state_with_readings(history_size: usize, readings: i64) -> ApiState {
+    let state = ApiState::new(history_size, 16);
+    for n in 0..readings {
+        state
+            .sink(reading_at("kitchen", n * 1000, n as f64))
+            .await
+            .expect("Should be able to sink");
+    }
+    state
+}
+
+fn updated_at(values: &[ApiEvent]) -> Vec<i64> {
+    values
+        .iter()
+        .map(|value| serde_json::to_value(value).unwrap()["updated_at"].as_i64().unwrap())
+        .collect()
+}
+
+#[actix_web::test]
+async fn history_keeps_the_last_readings_oldest_first() {
+    let state = state_with_readings(3, 5).await;
+
+    let history = state.history("kitchen", None, None).await.expect("Device should have a history");
+    assert_eq!(updated_at(&history), vec![2000, 3000, 4000], "Oldest readings should be evicted");
+    let latest = state.latest("kitchen").await.expect("Device should have a latest reading");
+    assert_eq!(updated_at(&[latest]), vec![4000], "Latest reading should be the last one");
+}
+
+#[actix_web::test]
+async fn history_is_filtered_by_since_then_limited_to_the_most_recent() {
+    let state = state_with_readings(10, 5).await;
+
+    let since = state.history("kitchen", Some(2000), None).await.unwrap();
+    assert_eq!(updated_at(&since), vec![2000, 3000, 4000], "Readings at since should be included");
+    let limited = state.history("kitchen", None, Some(2)).await.unwrap();
+    assert_eq!(updated_at(&limited), vec![3000, 4000], "Limit should keep the most recent readings");
+    let both = state.history("kitchen", Some(1000), Some(10)).await.unwrap();
+    assert_eq!(
+        updated_at(&both),
+        vec![1000, 2000, 3000, 4000],
+        "Limit larger than the history should keep it all"
+    );
+    let none = state.history("kitchen", Some(5000), None).await.unwrap();
+    assert!(none.is_empty(), "Nothing should be newer than the last reading");
+}
+
+#[actix_web::test]
+async fn history_size_zero_only_keeps_the_latest_reading() {
+    let state = state_with_readings(0, 3).await;
+
+    let history = state
+        .history("kitchen", None, None)
+        .await
+        .expect("Known device should have a history");
+    assert!(history.is_empty(), "History should not be kept");
+    assert!(state.latest("kitchen").await.is_some(), "Latest reading should still be served");
+}
+
+#[actix_web::test]
+async fn unknown_devices_have_no_history() {
+    let state = state_with_readings(10, 1).await;
+
+    assert!(
+        state.history("garage", None, None).await.is_none(),
+        "Unknown device should have no history"
+    );
+    assert!(state.latest("garage").await.is_none(), "Unknown device should have no reading");
+}
+
+#[actix_web::test]
+async fn device_routes_serve_the_latest_reading_and_the_history() {
+    let app = api_service(Arc::new(state_with_readings(10, 3).await)).await;
+
+    let req = test::TestRequest::get().uri("/devices/kitchen").to_request();
+    let body: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(
+        body,
+        json!({"name": "kitchen", "temperature": 2.0, "updated_at": 2000}),
+        "Latest reading should match"
+    );
+
+    let req = test::TestRequest::get()
+        .uri("/devices/kitchen/history?since=1000&limit=1")
+        .to_request();
+    let body: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(
+        body,
+        json!({"name": "kitchen", "values": [{"name": "kitchen", "temperature": 2.0, "updated_at": 2000}]}),
+        "History should match"
+    );
+}
+
+#[actix_web::test]
+async fn device_routes_answer_404_for_unknown_devices() {
+    let app = api_service(Arc::new(state_with_readings(10, 1).await)).await;
+
+    for uri in &["/devices/garage", "/devices/garage/history"] {
+        let res = test::call_service(&app, test::TestRequest::get().uri(uri).to_request()).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND, "[{}] should not be found", uri);
+    }
+}
@@ -0,0 +1,83 @@
+use crate::test_tools::*;
+use actix_web::test;
+use mqtt2influx::api::ApiState;
+use mqtt2influx_core::serde_json::{json, Value};
+use mqtt2influx_core::{Event, EventSink, FieldValue};
+use std::sync::Arc;
+
+async fn device_names(uri: &str, events: Vec<Event>) -> Vec<String> {
+    let state = Arc::new(ApiState::new(10, 16));
+    for event in events {
+        state.sink(event).await.expect("Should be able to sink");
+    }
+    let app = api_service(state).await;
+    let body: Value = test::call_and_read_body_json(&app, test::TestRequest::get().uri(uri).to_request()).await;
+    body["values"]
+        .as_array()
+        .expect("Values should be an array")
+        .iter()
+        .map(|value| value["name"].as_str().unwrap().to_string())
+        .collect()
+}
+
+fn with_battery(device: &str, battery: FieldValue) -> Event {
+    reading_at(device, 1000, 20.0).with_field("battery", battery)
+}
+
+#[actix_web::test]
+async fn devices_are_sorted_by_name() {
+    let events = vec![reading_at("kitchen", 1000, 20.0), reading_at("bedroom", 1000, 18.0)];
+    assert_eq!(
+        device_names("/devices", events).await,
+        vec!["bedroom", "kitchen"],
+        "Devices should be sorted"
+    );
+}
+
+#[actix_web::test]
+async fn battery_below_keeps_the_devices_with_a_lower_battery() {
+    let events = vec![
+        with_battery("door", FieldValue::Integer(15)),
+        with_battery("window", FieldValue::Float(19.5)),
+        with_battery("remote", FieldValue::Integer(20)),
+        with_battery("motion", FieldValue::Integer(80)),
+    ];
+    assert_eq!(
+        device_names("/devices?battery_below=20", events).await,
+        vec!["door", "window"],
+        "Only devices below the threshold should be listed"
+    );
+}
+
+#[actix_web::test]
+async fn battery_below_skips_devices_without_a_numeric_battery() {
+    let events = vec![
+        with_battery("door", FieldValue::Integer(5)),
+        reading_at("plug", 1000, 20.0),
+        with_battery("remote", FieldValue::String("low".to_string())),
+        with_battery("switch", FieldValue::Boolean(true)),
+    ];
+    assert_eq!(
+        device_names("/devices?battery_below=50", events).await,
+        vec!["door"],
+        "Devices without a numeric battery should not be listed"
+    );
+}
+
+#[actix_web::test]
+async fn fields_named_after_reserved_keys_are_not_served() {
+    let state = Arc::new(ApiState::new(10, 16));
+    let event = reading_at("kitchen", 1000, 20.0)
+        .with_field("name", FieldValue::String("Kitchen sensor".to_string()))
+        .with_field("status", FieldValue::String("ok".to_string()))
+        .with_field("updated_at", FieldValue::Integer(0));
+    state.sink(event).await.expect("Should be able to sink");
+
+    let app = api_service(state).await;
+    let body: Value = test::call_and_read_body_json(&app, test::TestRequest::get().uri("/devices/kitchen").to_request()).await;
+    assert_eq!(
+        body,
+        json!({"name": "kitchen", "temperature": 20.0, "updated_at": 1000}),
+        "Reserved fields should be dropped"
+    );
+}
@@ -0,0 +1,105 @@
+use crate::test_tools::*;
+use actix_web::body::to_bytes;
+use actix_web::test;
+use mqtt2influx::api::{ApiEvent, ApiState};
+use mqtt2influx_core::EventSink;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc::Receiver;
+
+async fn next(rx: &mut Receiver<ApiEvent>) -> Option<ApiEvent> {
+    tokio::time::timeout(Duration::from_secs(5), rx.recv())
+        .await
+        .expect("Timeout waiting for event")
+}
+
+#[actix_web::test]
+async fn subscribers_only_receive_their_device_when_filtered() {
+    let state = ApiState::new(10, 16);
+    let mut kitchen = state.subscribe(Some("kitchen".to_string()));
+    let mut everything = state.subscribe(None);
+
+    state.sink(reading_at("garage", 1000, 10.0)).await.unwrap();
+    state.sink(reading_at("kitchen", 2000, 20.0)).await.unwrap();
+
+    assert_eq!(
+        next(&mut kitchen).await.unwrap().name(),
+        "kitchen",
+        "Only kitchen should be streamed"
+    );
+    assert!(kitchen.try_recv().is_err(), "Nothing else should be streamed");
+    assert_eq!(
+        next(&mut everything).await.unwrap().name(),
+        "garage",
+        "Events should keep their order"
+    );
+    assert_eq!(
+        next(&mut everything).await.unwrap().name(),
+        "kitchen",
+        "Events should keep their order"
+    );
+}
+
+#[actix_web::test]
+async fn slow_subscribers_are_dropped_once_their_buffer_is_full() {
+    let state = ApiState::new(10, 2);
+    let mut slow = state.subscribe(None);
+
+    for n in 0..3 {
+        state.sink(reading_at("kitchen", n * 1000, n as f64)).await.unwrap();
+    }
+
+    assert!(next(&mut slow).await.is_some(), "Buffered events should be received");
+    assert!(next(&mut slow).await.is_some(), "Buffered events should be received");
+    assert!(next(&mut slow).await.is_none(), "Stream should end once the client is dropped");
+    assert_eq!(state.subscribers(), 0, "Slow client should be removed");
+}
+
+#[actix_web::test]
+async fn subscribers_that_went_away_are_removed() {
+    let state = ApiState::new(10, 16);
+    let gone = state.subscribe(None);
+    let filtered_gone = state.subscribe(Some("garage".to_string()));
+    let _connected = state.subscribe(None);
+    drop(gone);
+    drop(filtered_gone);
+
+    state.sink(reading_at("kitchen", 1000, 20.0)).await.unwrap();
+    assert_eq!(state.subscribers(), 1, "Only the connected client should be kept");
+}
+
+#[actix_web::test]
+async fn close_ends_the_streams() {
+    let state = ApiState::new(10, 16);
+    let mut rx = state.subscribe(None);
+
+    state.close().await.unwrap();
+    assert!(next(&mut rx).await.is_none(), "Stream should end on close");
+    assert_eq!(state.subscribers(), 0, "Subscribers should be cleared");
+}
+
+#[actix_web::test]
+async fn sse_streams_the_events_of_the_device() {
+    let state = Arc::new(ApiState::new(10, 16));
+    let app = api_service(state.clone()).await;
+
+    let req = test::TestRequest::get().uri("/stream?device=kitchen").to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(
+        res.headers().get("content-type").unwrap(),
+        "text/event-stream",
+        "Content type should be SSE"
+    );
+    state.sink(reading_at("garage", 1000, 10.0)).await.unwrap();
+    state.sink(reading_at("kitchen", 2000, 20.0)).await.unwrap();
+    state.close().await.unwrap();
+
+    let body = tokio::time::timeout(Duration::from_secs(5), to_bytes(res.into_body()))
+        .await
+        .expect("Stream should end on close")
+        .unwrap();
+    assert_eq!(
+        body, "data: {\"name\":\"kitchen\",\"temperature\":20.0,\"updated_at\":2000}\n\n",
+        "Only the kitchen event should be streamed"
+    );
+}
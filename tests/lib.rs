@@ -0,0 +1,9 @@
+pub mod test_tools;
+
+mod auth;
+mod conf;
+mod cors;
+mod device_history;
+mod devices;
+mod event_stream;
+mod monitoring;
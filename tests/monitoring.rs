@@ -0,0 +1,63 @@
+use crate::test_tools::*;
+use actix_web::http::{header, StatusCode};
+use actix_web::test::{self, TestRequest};
+use mqtt2influx::api::ApiState;
+use mqtt2influx_core::serde_json::{json, Value};
+use mqtt2influx_core::{EventSink, MeasuredSink, Metrics, QueueParameters, QueuedSink};
+use std::sync::Arc;
+
+/// Value of the `metric` sample in a Prometheus text body, `None` when it is missing.
+fn sample(body: &str, metric: &str) -> Option<f64> {
+    body.lines()
+        .filter_map(|line| line.split_once(' '))
+        .find(|(name, _)| *name == metric)
+        .and_then(|(_, value)| value.parse().ok())
+}
+
+#[actix_web::test]
+async fn queues_report_their_stats() {
+    let state = Arc::new(ApiState::new(10, 16));
+    let queue = Arc::new(QueuedSink::new(
+        "api",
+        state.clone(),
+        QueueParameters {
+            capacity: 10,
+            ..QueueParameters::default()
+        },
+    ));
+    queue
+        .sink(reading_at("kitchen", 1000, 20.0))
+        .await
+        .expect("Should be able to queue");
+    queue.flush().await.expect("Should be able to flush");
+
+    let app = monitored_api_service(state, vec![queue], Metrics::default()).await;
+    let res = test::call_service(&app, TestRequest::get().uri("/queues").to_request()).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let body: Value = test::read_body_json(res).await;
+    assert_eq!(
+        body,
+        json!({"queues": [{"name": "api", "depth": 0, "capacity": 10, "dropped": 0, "failed": 0}]}),
+        "Queue stats should match"
+    );
+}
+
+#[actix_web::test]
+async fn metrics_count_the_events_going_through_the_sinks() {
+    let state = Arc::new(ApiState::new(10, 16));
+    let metrics = Metrics::default();
+    let sink = MeasuredSink::new("api", state.clone(), metrics.clone());
+    let app = monitored_api_service(state, Vec::new(), metrics).await;
+    let calls = "mqtt2influx_sink_duration_seconds_count{sink=\"api\"}";
+
+    let res = test::call_service(&app, TestRequest::get().uri("/metrics").to_request()).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers().get(header::CONTENT_TYPE).unwrap(), "text/plain; version=0.0.4");
+    let body = String::from_utf8(test::read_body(res).await.to_vec()).unwrap();
+    assert_eq!(sample(&body, calls), None, "Sink should not be called yet");
+
+    sink.sink(reading_at("kitchen", 1000, 20.0)).await.expect("Should be able to sink");
+    let body = test::call_and_read_body(&app, TestRequest::get().uri("/metrics").to_request()).await;
+    let body = String::from_utf8(body.to_vec()).unwrap();
+    assert_eq!(sample(&body, calls), Some(1.0), "Sink call should be counted");
+}
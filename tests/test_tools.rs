@@ -0,0 +1,48 @@
+use actix_http::Request;
+use actix_web::body::BoxBody;
+use actix_web::dev::{Service, ServiceResponse};
+use actix_web::{test, web, App, Error};
+use config::{Config as CConfig, ConfigError, File, FileFormat};
+use mqtt2influx::api::{self, ApiState};
+use mqtt2influx::conf::{ApiConfig, Config};
+use mqtt2influx_core::chrono::{TimeZone, Utc};
+use mqtt2influx_core::{Event, FieldValue, Metrics, QueuedSink};
+use std::sync::Arc;
+
+/// Reading of `device` received `millis` after the epoch.
+pub fn reading_at(device: &str, millis: i64, temperature: f64) -> Event {
+    Event::new(device)
+        .with_received_at(Utc.timestamp_millis_opt(millis).unwrap())
+        .with_field("temperature", FieldValue::Float(temperature))
+}
+
+/// Every route of the API, without middlewares, serving `state`.
+pub async fn api_service(state: Arc<ApiState>) -> impl Service<Request, Response = ServiceResponse<BoxBody>, Error = Error> {
+    monitored_api_service(state, Vec::new(), Metrics::default()).await
+}
+
+/// Same as `api_service`, reporting `queues` and `metrics` on `/queues` and `/metrics`.
+pub async fn monitored_api_service(
+    state: Arc<ApiState>,
+    queues: Vec<Arc<QueuedSink>>,
+    metrics: Metrics,
+) -> impl Service<Request, Response = ServiceResponse<BoxBody>, Error = Error> {
+    test::init_service(
+        App::new()
+            .app_data(web::Data::new(ApiConfig::default()))
+            .app_data(web::Data::new(state))
+            .app_data(web::Data::new(queues))
+            .app_data(web::Data::new(metrics))
+            .configure(api::routes),
+    )
+    .await
+}
+
+/// Parses and validates a TOML config, without the environment overrides of `conf::load`.
+pub fn parse_config(toml: &str) -> Result<Config, ConfigError> {
+    let mut c = CConfig::new();
+    c.merge(File::from_str(toml, FileFormat::Toml))?;
+    let parsed: Config = c.try_into()?;
+    parsed.validate()?;
+    Ok(parsed)
+}